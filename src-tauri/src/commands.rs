@@ -1,36 +1,19 @@
-use tauri::State;
+use tauri::{AppHandle, Emitter, State};
 
-use crate::config::{self, AddressingStyle, Profile};
+use crate::config::{self, Profile, SyncJob};
+use crate::filter::FilterRules;
+use crate::run_log::{ReportFormat, RunLog, RunQuery, RunTrigger, SyncRun};
 use crate::s3_client::{
-    BucketInfo, DeleteObjectsResult, ListObjectsResult, ObjectMetadata, S3Client, S3ClientBuilder,
-    SyncDirection, SyncResult,
+    self, BucketInfo, BucketVersioning, CopyOverrides, CopyPrefixResult, DeleteObjectsResult,
+    ListObjectsResult, ListVersionsResult, ObjectMetadata, PrefixSummary, RenamePrefixResult,
+    S3Client, SyncDirection, SyncOptions, SyncPlan, SyncResult,
 };
-use crate::sync::{SyncManager, SyncState};
+use crate::schedule::{self, Scheduler};
+use crate::sync::{ConflictResolution, RemoteDeletePolicy, SyncManager, SyncState};
+use crate::transfer::{TransferJob, TransferManager};
 
 async fn get_client_for_profile(profile_id: &str) -> Result<S3Client, String> {
-    let config = config::load_config().map_err(|e| e.to_string())?;
-
-    let profile = config
-        .profiles
-        .iter()
-        .find(|p| p.id == profile_id)
-        .ok_or_else(|| format!("Profile not found: {}", profile_id))?;
-
-    let s3_profile = crate::s3_client::Profile {
-        name: profile.name.clone(),
-        access_key_id: profile.access_key_id.clone(),
-        secret_access_key: profile.secret_access_key.clone(),
-        region: profile.region.clone(),
-        endpoint: profile.endpoint.clone(),
-        path_style: profile.addressing_style == AddressingStyle::Path,
-        signature_version: match profile.signature_version {
-            config::SignatureVersion::V2 => crate::s3_client::SignatureVersion::V2,
-            config::SignatureVersion::V4 => crate::s3_client::SignatureVersion::V4,
-        },
-    };
-
-    S3ClientBuilder::new(s3_profile)
-        .build()
+    s3_client::client_for_profile(profile_id)
         .await
         .map_err(|e| e.to_string())
 }
@@ -96,6 +79,68 @@ pub async fn create_bucket(
         .map_err(|e| e.to_string())
 }
 
+/// Deletes a bucket. `confirm_name` must repeat the bucket name. With
+/// `empty_first`, every object version, delete marker and unfinished
+/// multipart upload is removed first; otherwise only an empty bucket can
+/// be deleted.
+#[tauri::command]
+pub async fn delete_bucket(
+    profile_id: String,
+    name: String,
+    confirm_name: String,
+    empty_first: bool,
+) -> Result<(), String> {
+    if confirm_name != name {
+        return Err(format!(
+            "Confirmation {:?} doesn't match bucket name {:?}",
+            confirm_name, name
+        ));
+    }
+
+    let client = get_client_for_profile(&profile_id).await?;
+    if empty_first {
+        let emptied = client
+            .empty_bucket(&name)
+            .await
+            .map_err(|e| e.to_string())?;
+        if let Some(error) = emptied.errors.first() {
+            return Err(format!(
+                "Couldn't empty bucket: {} objects weren't deleted ({}: {})",
+                emptied.errors.len(),
+                error.key,
+                error.message
+            ));
+        }
+    }
+
+    client.delete_bucket(&name).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_bucket_versioning(
+    profile_id: String,
+    bucket: String,
+) -> Result<BucketVersioning, String> {
+    let client = get_client_for_profile(&profile_id).await?;
+    client
+        .get_bucket_versioning(&bucket)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_bucket_versioning(
+    profile_id: String,
+    bucket: String,
+    enabled: bool,
+) -> Result<(), String> {
+    let client = get_client_for_profile(&profile_id).await?;
+    client
+        .set_bucket_versioning(&bucket, enabled)
+        .await
+        .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 pub async fn list_objects(
     profile_id: String,
@@ -117,19 +162,74 @@ pub async fn list_objects(
 }
 
 #[tauri::command]
-pub async fn download_object(
+pub async fn list_object_versions(
+    profile_id: String,
+    bucket: String,
+    prefix: Option<String>,
+    key_marker: Option<String>,
+    version_id_marker: Option<String>,
+    max_keys: i32,
+) -> Result<ListVersionsResult, String> {
+    let client = get_client_for_profile(&profile_id).await?;
+    client
+        .list_object_versions(
+            &bucket,
+            prefix.as_deref(),
+            key_marker.as_deref(),
+            version_id_marker.as_deref(),
+            max_keys,
+        )
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn restore_object_version(
+    profile_id: String,
+    bucket: String,
+    key: String,
+    version_id: String,
+) -> Result<Option<String>, String> {
+    let client = get_client_for_profile(&profile_id).await?;
+    client
+        .restore_object_version(&bucket, &key, &version_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn undelete_object(
     profile_id: String,
     bucket: String,
     key: String,
-    local_path: String,
 ) -> Result<(), String> {
     let client = get_client_for_profile(&profile_id).await?;
     client
-        .download_object(&bucket, &key, &local_path)
+        .undelete_object(&bucket, &key)
         .await
         .map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+pub async fn download_object(
+    profile_id: String,
+    bucket: String,
+    key: String,
+    local_path: String,
+    version_id: Option<String>,
+) -> Result<(), String> {
+    let client = get_client_for_profile(&profile_id).await?;
+    match version_id {
+        Some(version_id) => {
+            client
+                .download_object_version(&bucket, &key, &version_id, &local_path)
+                .await
+        }
+        None => client.download_object(&bucket, &key, &local_path).await,
+    }
+    .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 pub async fn create_folder(
     profile_id: String,
@@ -158,11 +258,7 @@ pub async fn upload_files(
             .and_then(|n| n.to_str())
             .ok_or_else(|| format!("Invalid file path: {}", file_path))?;
 
-        let key = if prefix.is_empty() {
-            file_name.to_string()
-        } else {
-            format!("{}/{}", prefix.trim_end_matches('/'), file_name)
-        };
+        let key = s3_client::join_key(&prefix, file_name);
 
         client
             .upload_file(&bucket, &key, &file_path)
@@ -179,10 +275,11 @@ pub async fn upload_folder(
     bucket: String,
     prefix: String,
     folder_path: String,
+    filters: Option<FilterRules>,
 ) -> Result<(), String> {
     let client = get_client_for_profile(&profile_id).await?;
     client
-        .upload_folder(&bucket, &prefix, &folder_path)
+        .upload_folder(&bucket, &prefix, &folder_path, &filters.unwrap_or_default())
         .await
         .map_err(|e| e.to_string())
 }
@@ -213,6 +310,206 @@ pub async fn delete_objects(
         .map_err(|e| e.to_string())
 }
 
+/// How much `delete_prefix` would remove, for the confirmation prompt.
+#[tauri::command]
+pub async fn preview_delete_prefix(
+    profile_id: String,
+    bucket: String,
+    prefix: String,
+) -> Result<PrefixSummary, String> {
+    let client = get_client_for_profile(&profile_id).await?;
+    client
+        .summarize_prefix(&bucket, &prefix)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[derive(Clone, serde::Serialize)]
+pub struct DeleteProgress {
+    pub bucket: String,
+    pub prefix: String,
+    pub current: u64,
+    pub total: u64,
+}
+
+/// Deletes a folder and everything under it, emitting `delete-progress`
+/// after each batch.
+#[tauri::command]
+pub async fn delete_prefix(
+    app_handle: AppHandle,
+    profile_id: String,
+    bucket: String,
+    prefix: String,
+) -> Result<DeleteObjectsResult, String> {
+    let client = get_client_for_profile(&profile_id).await?;
+    client
+        .delete_prefix(&bucket, &prefix, |current, total| {
+            let _ = app_handle.emit(
+                "delete-progress",
+                DeleteProgress {
+                    bucket: bucket.clone(),
+                    prefix: prefix.clone(),
+                    current,
+                    total,
+                },
+            );
+        })
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn copy_object(
+    profile_id: String,
+    source_bucket: String,
+    source_key: String,
+    bucket: String,
+    key: String,
+    overrides: Option<CopyOverrides>,
+) -> Result<Option<String>, String> {
+    let client = get_client_for_profile(&profile_id).await?;
+    client
+        .copy_object(
+            &source_bucket,
+            &source_key,
+            &bucket,
+            &key,
+            &overrides.unwrap_or_default(),
+        )
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn move_object(
+    profile_id: String,
+    source_bucket: String,
+    source_key: String,
+    bucket: String,
+    key: String,
+    overrides: Option<CopyOverrides>,
+) -> Result<Option<String>, String> {
+    let client = get_client_for_profile(&profile_id).await?;
+    client
+        .move_object(
+            &source_bucket,
+            &source_key,
+            &bucket,
+            &key,
+            &overrides.unwrap_or_default(),
+        )
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[derive(Clone, serde::Serialize)]
+pub struct RenameProgress {
+    pub bucket: String,
+    pub from_prefix: String,
+    pub to_prefix: String,
+    pub current: u64,
+    pub total: u64,
+    pub current_key: String,
+}
+
+/// Renames a folder by moving every object under it, emitting
+/// `rename-progress` after each object.
+#[tauri::command]
+pub async fn rename_prefix(
+    app_handle: AppHandle,
+    profile_id: String,
+    bucket: String,
+    from_prefix: String,
+    to_prefix: String,
+) -> Result<RenamePrefixResult, String> {
+    let client = get_client_for_profile(&profile_id).await?;
+    client
+        .rename_prefix(&bucket, &from_prefix, &to_prefix, |current, total, key| {
+            let _ = app_handle.emit(
+                "rename-progress",
+                RenameProgress {
+                    bucket: bucket.clone(),
+                    from_prefix: from_prefix.clone(),
+                    to_prefix: to_prefix.clone(),
+                    current,
+                    total,
+                    current_key: key.to_string(),
+                },
+            );
+        })
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[derive(Clone, serde::Serialize)]
+pub struct CopyProgress {
+    pub source_bucket: String,
+    pub bucket: String,
+    pub current: u64,
+    pub total: u64,
+    pub current_key: String,
+}
+
+/// Copies an object, or everything under `source_key` when it ends in `/`,
+/// from one profile and bucket to a prefix in another. Emits
+/// `copy-progress` after each object.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn copy_between_profiles(
+    app_handle: AppHandle,
+    source_profile_id: String,
+    source_bucket: String,
+    source_key: String,
+    profile_id: String,
+    bucket: String,
+    prefix: String,
+) -> Result<CopyPrefixResult, String> {
+    let source = get_client_for_profile(&source_profile_id).await?;
+    let client = get_client_for_profile(&profile_id).await?;
+    let progress = |current, total, key: &str| {
+        let _ = app_handle.emit(
+            "copy-progress",
+            CopyProgress {
+                source_bucket: source_bucket.clone(),
+                bucket: bucket.clone(),
+                current,
+                total,
+                current_key: key.to_string(),
+            },
+        );
+    };
+
+    if source_key.is_empty() || source_key.ends_with('/') {
+        return client
+            .copy_prefix_from(
+                &source,
+                &source_bucket,
+                &source_key,
+                &bucket,
+                &prefix,
+                progress,
+            )
+            .await
+            .map_err(|e| e.to_string());
+    }
+
+    let name = source_key.rsplit('/').next().unwrap_or(&source_key);
+    let key = match prefix.trim_end_matches('/') {
+        "" => name.to_string(),
+        prefix => format!("{}/{}", prefix, name),
+    };
+    client
+        .copy_object_from(&source, &source_bucket, &source_key, &bucket, &key)
+        .await
+        .map_err(|e| e.to_string())?;
+    progress(1, 1, &source_key);
+
+    Ok(CopyPrefixResult {
+        copied: 1,
+        errors: vec![],
+    })
+}
+
 #[tauri::command]
 pub async fn presign_url(
     profile_id: String,
@@ -229,26 +526,126 @@ pub async fn presign_url(
 
 #[tauri::command]
 pub async fn sync_folder(
+    run_log: State<'_, RunLog>,
     profile_id: String,
     bucket: String,
     prefix: String,
     local_path: String,
     direction: String,
+    options: Option<SyncOptions>,
 ) -> Result<SyncResult, String> {
     let client = get_client_for_profile(&profile_id).await?;
 
-    let sync_direction = match direction.as_str() {
-        "local_to_remote" => SyncDirection::LocalToRemote,
-        "remote_to_local" => SyncDirection::RemoteToLocal,
-        _ => return Err(format!("Invalid sync direction: {}", direction)),
-    };
+    let sync_direction = parse_sync_direction(&direction)?;
+
+    let mut run = SyncRun::new(
+        RunTrigger::Manual,
+        &profile_id,
+        &bucket,
+        &prefix,
+        &local_path,
+    );
+    run.direction = Some(sync_direction);
+    let outcome = client
+        .sync_folder(
+            &bucket,
+            &prefix,
+            &local_path,
+            sync_direction,
+            &options.unwrap_or_default(),
+        )
+        .await;
+    run.finish(&outcome);
+    run_log.record(run).await;
+
+    outcome.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn plan_sync(
+    profile_id: String,
+    bucket: String,
+    prefix: String,
+    local_path: String,
+    direction: String,
+    options: Option<SyncOptions>,
+) -> Result<SyncPlan, String> {
+    let client = get_client_for_profile(&profile_id).await?;
+    let sync_direction = parse_sync_direction(&direction)?;
 
     client
-        .sync_folder(&bucket, &prefix, &local_path, sync_direction)
+        .plan_sync(
+            &bucket,
+            &prefix,
+            &local_path,
+            sync_direction,
+            &options.unwrap_or_default(),
+        )
         .await
         .map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+pub async fn execute_sync_plan(
+    run_log: State<'_, RunLog>,
+    profile_id: String,
+    plan: SyncPlan,
+) -> Result<SyncResult, String> {
+    let client = get_client_for_profile(&profile_id).await?;
+
+    let mut run = SyncRun::new(
+        RunTrigger::Manual,
+        &profile_id,
+        &plan.bucket,
+        &plan.prefix,
+        &plan.local_path,
+    );
+    run.direction = Some(plan.direction);
+    let outcome = client.execute_sync_plan(&plan).await;
+    run.finish(&outcome);
+    run_log.record(run).await;
+
+    outcome.map_err(|e| e.to_string())
+}
+
+fn parse_sync_direction(direction: &str) -> Result<SyncDirection, String> {
+    match direction {
+        "local_to_remote" => Ok(SyncDirection::LocalToRemote),
+        "remote_to_local" => Ok(SyncDirection::RemoteToLocal),
+        "bidirectional" => Ok(SyncDirection::Bidirectional),
+        _ => Err(format!("Invalid sync direction: {}", direction)),
+    }
+}
+
+#[tauri::command]
+pub async fn resolve_sync_conflicts(
+    run_log: State<'_, RunLog>,
+    profile_id: String,
+    bucket: String,
+    prefix: String,
+    local_path: String,
+    resolutions: std::collections::HashMap<String, ConflictResolution>,
+) -> Result<SyncResult, String> {
+    let client = get_client_for_profile(&profile_id).await?;
+    let resolutions: Vec<(String, ConflictResolution)> = resolutions.into_iter().collect();
+
+    let mut run = SyncRun::new(
+        RunTrigger::Manual,
+        &profile_id,
+        &bucket,
+        &prefix,
+        &local_path,
+    );
+    run.direction = Some(SyncDirection::Bidirectional);
+    let outcome = client
+        .resolve_sync_conflicts(&bucket, &prefix, &local_path, &resolutions)
+        .await;
+    run.finish(&outcome);
+    run_log.record(run).await;
+
+    outcome.map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 pub async fn get_object_content_type(
     profile_id: String,
@@ -273,10 +670,11 @@ pub async fn get_object_preview(
     profile_id: String,
     bucket: String,
     key: String,
+    version_id: Option<String>,
 ) -> Result<PreviewData, String> {
     let client = get_client_for_profile(&profile_id).await?;
     let (bytes, content_type) = client
-        .get_object_bytes(&bucket, &key)
+        .get_object_bytes(&bucket, &key, version_id.as_deref())
         .await
         .map_err(|e| e.to_string())?;
     
@@ -309,15 +707,41 @@ pub async fn get_object_preview(
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn start_keep_sync(
     sync_manager: State<'_, SyncManager>,
     profile_id: String,
     bucket: String,
     prefix: String,
     local_path: String,
+    debounce_ms: Option<u64>,
+    filters: Option<FilterRules>,
+    poll_interval_secs: Option<u64>,
+    remote_deletes: Option<RemoteDeletePolicy>,
 ) -> Result<String, String> {
+    let debounce_ms = debounce_ms.unwrap_or(crate::sync::DEFAULT_DEBOUNCE_MS);
     sync_manager
-        .start_keep_sync(&profile_id, &bucket, &prefix, &local_path)
+        .start_keep_sync(
+            &profile_id,
+            &bucket,
+            &prefix,
+            &local_path,
+            debounce_ms,
+            filters.unwrap_or_default(),
+            poll_interval_secs,
+            remote_deletes.unwrap_or_default(),
+        )
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn poll_keep_sync(
+    sync_manager: State<'_, SyncManager>,
+    sync_id: String,
+) -> Result<(), String> {
+    sync_manager
+        .poll_keep_sync(&sync_id)
         .await
         .map_err(|e| e.to_string())
 }
@@ -339,3 +763,190 @@ pub async fn get_active_syncs(
 ) -> Result<Vec<SyncState>, String> {
     Ok(sync_manager.get_active_syncs().await)
 }
+
+#[tauri::command]
+pub async fn enqueue_uploads(
+    transfer_manager: State<'_, TransferManager>,
+    profile_id: String,
+    bucket: String,
+    prefix: String,
+    file_paths: Vec<String>,
+) -> Result<Vec<TransferJob>, String> {
+    let mut jobs = Vec::with_capacity(file_paths.len());
+
+    for file_path in file_paths {
+        let file_name = std::path::Path::new(&file_path)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .ok_or_else(|| format!("Invalid file path: {}", file_path))?;
+
+        let key = s3_client::join_key(&prefix, file_name);
+
+        let job = transfer_manager
+            .enqueue_upload(&profile_id, &bucket, &key, &file_path)
+            .await
+            .map_err(|e| e.to_string())?;
+        jobs.push(job);
+    }
+
+    Ok(jobs)
+}
+
+#[tauri::command]
+pub async fn enqueue_folder_upload(
+    transfer_manager: State<'_, TransferManager>,
+    profile_id: String,
+    bucket: String,
+    prefix: String,
+    folder_path: String,
+    filters: Option<FilterRules>,
+) -> Result<Vec<TransferJob>, String> {
+    transfer_manager
+        .enqueue_folder_upload(
+            &profile_id,
+            &bucket,
+            &prefix,
+            &folder_path,
+            &filters.unwrap_or_default(),
+        )
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn enqueue_download(
+    transfer_manager: State<'_, TransferManager>,
+    profile_id: String,
+    bucket: String,
+    key: String,
+    local_path: String,
+) -> Result<TransferJob, String> {
+    transfer_manager
+        .enqueue_download(&profile_id, &bucket, &key, &local_path)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn list_transfers(
+    transfer_manager: State<'_, TransferManager>,
+) -> Result<Vec<TransferJob>, String> {
+    Ok(transfer_manager.list_transfers().await)
+}
+
+#[tauri::command]
+pub async fn pause_transfer(
+    transfer_manager: State<'_, TransferManager>,
+    job_id: String,
+) -> Result<TransferJob, String> {
+    transfer_manager
+        .pause(&job_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn resume_transfer(
+    transfer_manager: State<'_, TransferManager>,
+    job_id: String,
+) -> Result<TransferJob, String> {
+    transfer_manager
+        .resume(&job_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn cancel_transfer(
+    transfer_manager: State<'_, TransferManager>,
+    job_id: String,
+) -> Result<TransferJob, String> {
+    transfer_manager
+        .cancel(&job_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn retry_transfer(
+    transfer_manager: State<'_, TransferManager>,
+    job_id: String,
+) -> Result<TransferJob, String> {
+    transfer_manager
+        .retry(&job_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn clear_finished_transfers(
+    transfer_manager: State<'_, TransferManager>,
+) -> Result<(), String> {
+    transfer_manager
+        .clear_finished()
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn list_sync_jobs() -> Result<Vec<SyncJob>, String> {
+    let config = config::load_config().map_err(|e| e.to_string())?;
+    Ok(config.sync_jobs)
+}
+
+#[tauri::command]
+pub fn save_sync_job(job: SyncJob) -> Result<SyncJob, String> {
+    if let Some(expression) = &job.schedule {
+        schedule::parse_schedule(expression).map_err(|e| e.to_string())?;
+    }
+
+    let mut config = config::load_config().map_err(|e| e.to_string())?;
+    let job = config::save_sync_job(&mut config, job);
+    config::save_config(&config).map_err(|e| e.to_string())?;
+    Ok(job)
+}
+
+#[tauri::command]
+pub fn delete_sync_job(id: String) -> Result<(), String> {
+    let mut config = config::load_config().map_err(|e| e.to_string())?;
+    config::delete_sync_job(&mut config, &id).map_err(|e| e.to_string())?;
+    config::save_config(&config).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn run_sync_job(
+    scheduler: State<'_, Scheduler>,
+    job_id: String,
+) -> Result<SyncRun, String> {
+    scheduler
+        .run_job_now(&job_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn list_sync_runs(
+    run_log: State<'_, RunLog>,
+    query: Option<RunQuery>,
+) -> Result<Vec<SyncRun>, String> {
+    Ok(run_log.runs(&query.unwrap_or_default()).await)
+}
+
+#[tauri::command]
+pub async fn get_sync_run(run_log: State<'_, RunLog>, run_id: String) -> Result<SyncRun, String> {
+    run_log.run(&run_id).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn export_sync_runs(
+    run_log: State<'_, RunLog>,
+    run_ids: Vec<String>,
+    format: ReportFormat,
+    path: String,
+) -> Result<(), String> {
+    run_log
+        .export(&run_ids, format, &path)
+        .await
+        .map_err(|e| e.to_string())
+}
@@ -4,6 +4,10 @@ use std::path::PathBuf;
 use thiserror::Error;
 use uuid::Uuid;
 
+use crate::filter::FilterRules;
+use crate::s3_client::{SyncDirection, SyncOptions};
+use crate::sync::RemoteDeletePolicy;
+
 #[derive(Error, Debug)]
 pub enum ConfigError {
     #[error("Failed to read config file: {0}")]
@@ -12,6 +16,8 @@ pub enum ConfigError {
     ParseError(#[from] serde_json::Error),
     #[error("Profile not found: {0}")]
     ProfileNotFound(String),
+    #[error("Sync job not found: {0}")]
+    SyncJobNotFound(String),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -54,9 +60,80 @@ pub struct Profile {
     pub signature_version: SignatureVersion,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TransferSettings {
+    pub multipart_threshold_mb: u64,
+    pub multipart_part_size_mb: u64,
+    pub multipart_concurrency: usize,
+    pub multipart_max_retries: u32,
+    /// How many queued transfers run at the same time.
+    pub max_concurrent_transfers: usize,
+    /// How many files a sync hashes at the same time.
+    pub sync_hash_concurrency: usize,
+    /// How many files a sync uploads or downloads at the same time.
+    pub sync_transfer_concurrency: usize,
+}
+
+impl Default for TransferSettings {
+    fn default() -> Self {
+        Self {
+            multipart_threshold_mb: 64,
+            multipart_part_size_mb: 16,
+            multipart_concurrency: 4,
+            multipart_max_retries: 3,
+            max_concurrent_transfers: 3,
+            sync_hash_concurrency: 8,
+            sync_transfer_concurrency: 4,
+        }
+    }
+}
+
+/// A KeepSync that is started again whenever the app starts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeepSyncJob {
+    /// Also the sync id while the job is running.
+    pub id: String,
+    pub profile_id: String,
+    pub bucket: String,
+    pub prefix: String,
+    pub local_path: String,
+    pub debounce_ms: u64,
+    #[serde(default)]
+    pub filters: FilterRules,
+    #[serde(default)]
+    pub poll_interval_secs: Option<u64>,
+    #[serde(default)]
+    pub remote_deletes: RemoteDeletePolicy,
+}
+
+/// A named sync saved to be run on a schedule or on demand.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncJob {
+    pub id: String,
+    pub name: String,
+    pub profile_id: String,
+    pub bucket: String,
+    pub prefix: String,
+    pub local_path: String,
+    pub direction: SyncDirection,
+    #[serde(default)]
+    pub options: SyncOptions,
+    /// Cron expression for when the job runs by itself. Without one the
+    /// job only runs on demand.
+    #[serde(default)]
+    pub schedule: Option<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Config {
     pub profiles: Vec<Profile>,
+    #[serde(default)]
+    pub transfer: TransferSettings,
+    #[serde(default)]
+    pub keep_syncs: Vec<KeepSyncJob>,
+    #[serde(default)]
+    pub sync_jobs: Vec<SyncJob>,
 }
 
 pub fn get_config_path() -> PathBuf {
@@ -133,5 +210,32 @@ pub fn delete_profile(config: &mut Config, profile_id: &str) -> Result<(), Confi
         .ok_or_else(|| ConfigError::ProfileNotFound(profile_id.to_string()))?;
 
     config.profiles.remove(index);
+    config.keep_syncs.retain(|job| job.profile_id != profile_id);
+    config.sync_jobs.retain(|job| job.profile_id != profile_id);
+    Ok(())
+}
+
+/// Adds `job`, or replaces the saved job with the same id. A job without an
+/// id is given one.
+pub fn save_sync_job(config: &mut Config, mut job: SyncJob) -> SyncJob {
+    if job.id.is_empty() {
+        job.id = Uuid::new_v4().to_string();
+    }
+
+    match config.sync_jobs.iter_mut().find(|j| j.id == job.id) {
+        Some(existing) => *existing = job.clone(),
+        None => config.sync_jobs.push(job.clone()),
+    }
+    job
+}
+
+pub fn delete_sync_job(config: &mut Config, job_id: &str) -> Result<(), ConfigError> {
+    let index = config
+        .sync_jobs
+        .iter()
+        .position(|j| j.id == job_id)
+        .ok_or_else(|| ConfigError::SyncJobNotFound(job_id.to_string()))?;
+
+    config.sync_jobs.remove(index);
     Ok(())
 }
@@ -0,0 +1,285 @@
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{Context, Result};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
+
+use crate::s3_client;
+
+/// Gitignore-style file in the root of a local folder listing paths that
+/// are never uploaded or downloaded.
+pub const IGNORE_FILE_NAME: &str = ".s3ignore";
+
+/// Glob patterns chosen for a sync job or folder upload. A pattern without
+/// a `/` matches a file or folder name at any depth; one with a `/` is
+/// matched against the whole path relative to the root.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FilterRules {
+    /// When not empty, only files matching one of these are transferred.
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+/// What a sync does with symbolic links in the local folder. Sockets,
+/// FIFOs and device files are always left out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SymlinkPolicy {
+    #[default]
+    Skip,
+    /// Sync whatever a link points to as if it were inside the folder.
+    Follow,
+    /// Upload each link as a small object holding its target, and turn
+    /// such objects back into links on download.
+    Store,
+}
+
+/// Decides which paths under a local root (or keys under a prefix) take
+/// part in a transfer, combining `FilterRules` with the root's `.s3ignore`.
+pub struct PathFilter {
+    root: PathBuf,
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+    ignore: Gitignore,
+    symlinks: SymlinkPolicy,
+}
+
+impl PathFilter {
+    pub fn new(root: &Path, rules: &FilterRules) -> Result<Self> {
+        let include = if rules.include.is_empty() {
+            None
+        } else {
+            Some(build_glob_set(&rules.include)?)
+        };
+
+        let mut builder = GitignoreBuilder::new(root);
+        let ignore_file = root.join(IGNORE_FILE_NAME);
+        if ignore_file.is_file() {
+            if let Some(err) = builder.add(&ignore_file) {
+                return Err(err).context("Failed to read .s3ignore");
+            }
+        }
+
+        Ok(Self {
+            root: root.to_path_buf(),
+            include,
+            exclude: build_glob_set(&rules.exclude)?,
+            ignore: builder.build().context("Failed to parse .s3ignore")?,
+            symlinks: SymlinkPolicy::default(),
+        })
+    }
+
+    /// Sets how `files` treats symbolic links. They are skipped by default.
+    pub fn symlinks(mut self, symlinks: SymlinkPolicy) -> Self {
+        self.symlinks = symlinks;
+        self
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// `/`-separated path of `path` relative to the root, or `None` if it
+    /// lies outside it.
+    pub fn relative_path(&self, path: &Path) -> Option<String> {
+        let relative = path.strip_prefix(&self.root).ok()?;
+        let parts: Vec<String> = relative
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(part) => Some(part.to_string_lossy().to_string()),
+                _ => None,
+            })
+            .collect();
+        Some(parts.join("/"))
+    }
+
+    /// Whether an exclude pattern or `.s3ignore` rule rules out `relative`
+    /// or any folder above it.
+    pub fn is_excluded(&self, relative: &str, is_dir: bool) -> bool {
+        let mut ancestor = 0;
+        while let Some(offset) = relative[ancestor..].find('/') {
+            ancestor += offset;
+            if self.matches_exclusion(&relative[..ancestor], true) {
+                return true;
+            }
+            ancestor += 1;
+        }
+
+        self.matches_exclusion(relative, is_dir)
+    }
+
+    /// Whether the file at `relative` should be transferred.
+    pub fn is_included(&self, relative: &str) -> bool {
+        if self.is_excluded(relative, false) {
+            return false;
+        }
+
+        self.include
+            .as_ref()
+            .map_or(true, |include| include.is_match(relative))
+    }
+
+    /// Files under the root that pass the filter, keyed by their relative
+    /// path. In-progress downloads are always left out, and symlinks are
+    /// handled as the filter's `SymlinkPolicy` says.
+    pub fn files(&self) -> Vec<(String, PathBuf)> {
+        self.files_under(&self.root)
+    }
+
+    /// Like `files`, but only walks `dir`, which must be inside the root.
+    pub fn files_under(&self, dir: &Path) -> Vec<(String, PathBuf)> {
+        let walker = WalkDir::new(dir)
+            .follow_links(self.symlinks == SymlinkPolicy::Follow)
+            .into_iter()
+            .filter_entry(|entry| {
+                if !entry.file_type().is_dir() {
+                    return true;
+                }
+                match self.relative_path(entry.path()) {
+                    Some(relative) if !relative.is_empty() => !self.is_excluded(&relative, true),
+                    _ => true,
+                }
+            });
+
+        walker
+            .filter_map(|e| e.ok())
+            .filter(|entry| {
+                let stored_link = self.symlinks == SymlinkPolicy::Store && entry.path_is_symlink();
+                (entry.file_type().is_file() || stored_link)
+                    && !s3_client::is_partial_download(entry.path())
+            })
+            .filter_map(|entry| {
+                let relative = self.relative_path(entry.path())?;
+                self.is_included(&relative)
+                    .then(|| (relative, entry.into_path()))
+            })
+            .collect()
+    }
+
+    fn matches_exclusion(&self, relative: &str, is_dir: bool) -> bool {
+        self.exclude.is_match(relative) || self.ignore.matched(relative, is_dir).is_ignore()
+    }
+}
+
+fn build_glob_set(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let pattern = pattern.trim().trim_start_matches("./");
+        if pattern.is_empty() {
+            continue;
+        }
+
+        // Like gitignore, a bare name matches at any depth.
+        let pattern = if pattern.contains('/') {
+            pattern.trim_start_matches('/').to_string()
+        } else {
+            format!("**/{}", pattern)
+        };
+
+        let glob = GlobBuilder::new(&pattern)
+            .literal_separator(true)
+            .build()
+            .with_context(|| format!("Invalid glob pattern: {}", pattern))?;
+        builder.add(glob);
+    }
+    builder.build().context("Failed to build glob patterns")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(include: &[&str], exclude: &[&str]) -> PathFilter {
+        let rules = FilterRules {
+            include: include.iter().map(|s| s.to_string()).collect(),
+            exclude: exclude.iter().map(|s| s.to_string()).collect(),
+        };
+        PathFilter::new(Path::new("/nonexistent/root"), &rules).unwrap()
+    }
+
+    #[test]
+    fn test_bare_names_match_at_any_depth() {
+        let filter = filter(&[], &["node_modules", ".DS_Store"]);
+        assert!(!filter.is_included("node_modules/react/index.js"));
+        assert!(!filter.is_included("web/node_modules/react/index.js"));
+        assert!(!filter.is_included("photos/.DS_Store"));
+        assert!(filter.is_included("web/src/index.js"));
+    }
+
+    #[test]
+    fn test_patterns_with_slash_are_anchored() {
+        let filter = filter(&[], &["build/*.o"]);
+        assert!(!filter.is_included("build/main.o"));
+        assert!(filter.is_included("src/build/main.o"));
+        assert!(filter.is_included("build/sub/main.o"));
+    }
+
+    #[test]
+    fn test_include_limits_files_but_not_folders() {
+        let filter = filter(&["*.jpg"], &[]);
+        assert!(filter.is_included("a/b/photo.jpg"));
+        assert!(!filter.is_included("a/b/notes.txt"));
+        assert!(!filter.is_excluded("a/b", true));
+    }
+
+    #[test]
+    fn test_s3ignore_uses_gitignore_rules() {
+        let root = std::env::temp_dir().join(format!("s3gui-filter-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join(IGNORE_FILE_NAME), "*.log\n!keep.log\n/target/\n").unwrap();
+
+        let filter = PathFilter::new(&root, &FilterRules::default()).unwrap();
+        assert!(!filter.is_included("logs/app.log"));
+        assert!(filter.is_included("logs/keep.log"));
+        assert!(!filter.is_included("target/debug/app"));
+        assert!(filter.is_included("src/target/notes.txt"));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlink_policy_decides_which_links_are_listed() {
+        let root = std::env::temp_dir().join(format!("s3gui-links-{}", std::process::id()));
+        let outside = root.with_extension("outside");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::write(root.join("file.txt"), "a").unwrap();
+        std::fs::write(outside.join("shared.txt"), "b").unwrap();
+        std::os::unix::fs::symlink("file.txt", root.join("link.txt")).unwrap();
+        std::os::unix::fs::symlink(&outside, root.join("shared")).unwrap();
+
+        let listed = |symlinks| {
+            let filter = PathFilter::new(&root, &FilterRules::default())
+                .unwrap()
+                .symlinks(symlinks);
+            let mut paths: Vec<String> = filter.files().into_iter().map(|(p, _)| p).collect();
+            paths.sort();
+            paths
+        };
+
+        assert_eq!(listed(SymlinkPolicy::Skip), ["file.txt"]);
+        assert_eq!(
+            listed(SymlinkPolicy::Follow),
+            ["file.txt", "link.txt", "shared/shared.txt"]
+        );
+        assert_eq!(
+            listed(SymlinkPolicy::Store),
+            ["file.txt", "link.txt", "shared"]
+        );
+
+        std::fs::remove_dir_all(&root).unwrap();
+        std::fs::remove_dir_all(&outside).unwrap();
+    }
+
+    #[test]
+    fn test_relative_path_uses_forward_slashes() {
+        let filter = filter(&[], &[]);
+        let path = Path::new("/nonexistent/root").join("a").join("b.txt");
+        assert_eq!(filter.relative_path(&path).as_deref(), Some("a/b.txt"));
+        assert_eq!(filter.relative_path(Path::new("/elsewhere/b.txt")), None);
+    }
+}
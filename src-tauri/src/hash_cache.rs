@@ -0,0 +1,253 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// What a cached hash was computed against. Any difference means the file
+/// may have changed and has to be read again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileStat {
+    pub size: u64,
+    /// Modification time in milliseconds since the Unix epoch.
+    pub mtime: i64,
+    /// Zero on platforms without inode numbers.
+    pub inode: u64,
+}
+
+impl FileStat {
+    pub fn from_metadata(metadata: &fs::Metadata) -> Self {
+        let mtime = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_millis() as i64);
+
+        Self {
+            size: metadata.len(),
+            mtime,
+            inode: inode(metadata),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn inode(metadata: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.ino()
+}
+
+#[cfg(not(unix))]
+fn inode(_metadata: &fs::Metadata) -> u64 {
+    0
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedHashes {
+    stat: FileStat,
+    #[serde(default)]
+    md5: Option<String>,
+    /// Multipart ETags keyed by the part size they were computed with.
+    #[serde(default)]
+    multipart: BTreeMap<u64, String>,
+}
+
+/// MD5s and multipart ETags of local files, stored in
+/// `~/.s3gui/hash-cache.json` so unchanged files aren't re-read on every
+/// sync.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct HashCache {
+    entries: BTreeMap<String, CachedHashes>,
+    #[serde(skip)]
+    dirty: bool,
+}
+
+pub fn get_cache_path() -> PathBuf {
+    let home = dirs::home_dir().expect("Failed to get home directory");
+    home.join(".s3gui").join("hash-cache.json")
+}
+
+impl HashCache {
+    /// Loads the cache as written. Entries aren't checked against the disk
+    /// here; a lookup only trusts one whose stat still matches the file.
+    pub fn load() -> Result<Self> {
+        let path = get_cache_path();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(&path).context("Failed to read hash cache")?;
+        serde_json::from_str(&content).context("Failed to parse hash cache")
+    }
+
+    /// The entries to write if anything changed since the last snapshot.
+    fn take_snapshot(&mut self) -> Option<BTreeMap<String, CachedHashes>> {
+        if !self.dirty {
+            return None;
+        }
+        self.dirty = false;
+        Some(self.entries.clone())
+    }
+
+    pub fn md5(&mut self, path: &Path, stat: &FileStat) -> Option<String> {
+        self.entry(path, stat)?.md5.clone()
+    }
+
+    pub fn multipart_etag(
+        &mut self,
+        path: &Path,
+        stat: &FileStat,
+        part_size: u64,
+    ) -> Option<String> {
+        self.entry(path, stat)?.multipart.get(&part_size).cloned()
+    }
+
+    pub fn insert_md5(&mut self, path: &Path, stat: &FileStat, md5: String) {
+        self.entry_mut(path, stat).md5 = Some(md5);
+    }
+
+    pub fn insert_multipart_etag(
+        &mut self,
+        path: &Path,
+        stat: &FileStat,
+        part_size: u64,
+        etag: String,
+    ) {
+        self.entry_mut(path, stat).multipart.insert(part_size, etag);
+    }
+
+    /// The entry for `path` if it was computed against `stat`. One computed
+    /// against an older version of the file is dropped.
+    fn entry(&mut self, path: &Path, stat: &FileStat) -> Option<&CachedHashes> {
+        let key = path.to_string_lossy();
+        if self
+            .entries
+            .get(key.as_ref())
+            .is_some_and(|entry| entry.stat != *stat)
+        {
+            self.entries.remove(key.as_ref());
+            self.dirty = true;
+        }
+        self.entries.get(key.as_ref())
+    }
+
+    /// The entry for `path`, emptied first if it was computed against a
+    /// different stat.
+    fn entry_mut(&mut self, path: &Path, stat: &FileStat) -> &mut CachedHashes {
+        self.dirty = true;
+        let entry = self
+            .entries
+            .entry(path.to_string_lossy().to_string())
+            .or_insert_with(|| CachedHashes {
+                stat: *stat,
+                md5: None,
+                multipart: BTreeMap::new(),
+            });
+        if entry.stat != *stat {
+            *entry = CachedHashes {
+                stat: *stat,
+                md5: None,
+                multipart: BTreeMap::new(),
+            };
+        }
+        entry
+    }
+}
+
+/// Runs `f` on the cache shared by every sync in this process, which is
+/// loaded on first use.
+pub fn with_shared<T>(f: impl FnOnce(&mut HashCache) -> T) -> T {
+    static CACHE: OnceLock<Mutex<HashCache>> = OnceLock::new();
+    let cache = CACHE.get_or_init(|| Mutex::new(HashCache::load().unwrap_or_default()));
+    let mut guard = cache.lock().unwrap_or_else(|e| e.into_inner());
+    f(&mut guard)
+}
+
+/// Persists the shared cache. The file is written from a snapshot on a
+/// blocking thread, leaving out files that no longer exist, so lookups
+/// aren't held up. Losing it only costs rehashing, so callers can ignore
+/// failures.
+pub async fn save_shared() -> Result<()> {
+    // Saves run one at a time so an older snapshot never lands last.
+    static SAVING: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+    let _saving = SAVING.lock().await;
+
+    let Some(mut entries) = with_shared(|cache| cache.take_snapshot()) else {
+        return Ok(());
+    };
+
+    let written = tokio::task::spawn_blocking(move || {
+        entries.retain(|path, _| Path::new(path).is_file());
+        write_entries(entries)
+    })
+    .await
+    .context("Hash cache writer panicked")
+    .and_then(|result| result);
+
+    if written.is_err() {
+        with_shared(|cache| cache.dirty = true);
+    }
+    written
+}
+
+fn write_entries(entries: BTreeMap<String, CachedHashes>) -> Result<()> {
+    let path = get_cache_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).context("Failed to create hash cache directory")?;
+    }
+
+    let cache = HashCache {
+        entries,
+        dirty: false,
+    };
+    let content = serde_json::to_string(&cache)?;
+    fs::write(&path, content).context("Failed to write hash cache")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stat(size: u64, mtime: i64, inode: u64) -> FileStat {
+        FileStat { size, mtime, inode }
+    }
+
+    #[test]
+    fn test_changed_stat_invalidates_entry() {
+        let path = Path::new("/data/photo.jpg");
+        let mut cache = HashCache::default();
+        cache.insert_md5(path, &stat(10, 1000, 7), "abc".to_string());
+
+        assert_eq!(cache.md5(path, &stat(10, 1000, 7)).as_deref(), Some("abc"));
+        assert_eq!(cache.md5(path, &stat(10, 2000, 7)), None);
+        assert_eq!(cache.md5(path, &stat(10, 1000, 8)), None);
+        assert_eq!(cache.md5(path, &stat(11, 1000, 7)), None);
+    }
+
+    #[test]
+    fn test_multipart_etags_kept_per_part_size() {
+        let path = Path::new("/data/video.mp4");
+        let current = stat(100, 1000, 1);
+        let mut cache = HashCache::default();
+        cache.insert_md5(path, &current, "abc".to_string());
+        cache.insert_multipart_etag(path, &current, 8, "def-13".to_string());
+        cache.insert_multipart_etag(path, &current, 16, "ghi-7".to_string());
+
+        assert_eq!(
+            cache.multipart_etag(path, &current, 8).as_deref(),
+            Some("def-13")
+        );
+        assert_eq!(
+            cache.multipart_etag(path, &current, 16).as_deref(),
+            Some("ghi-7")
+        );
+        assert_eq!(cache.md5(path, &current).as_deref(), Some("abc"));
+
+        // Hashing a new version of the file forgets everything about the old one.
+        cache.insert_md5(path, &stat(100, 2000, 1), "jkl".to_string());
+        assert_eq!(cache.multipart_etag(path, &stat(100, 2000, 1), 8), None);
+    }
+}
@@ -1,11 +1,20 @@
 mod commands;
 mod config;
+mod filter;
+mod hash_cache;
+mod run_log;
 mod s3_client;
+mod schedule;
 mod sync;
+mod sync_db;
+mod transfer;
 
 use commands::*;
+use run_log::RunLog;
+use schedule::Scheduler;
 use sync::SyncManager;
 use tauri::Manager;
+use transfer::TransferManager;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -15,8 +24,29 @@ pub fn run() {
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_clipboard_manager::init())
         .setup(|app| {
-            let sync_manager = SyncManager::new(app.handle().clone());
+            let run_log = RunLog::load();
+
+            let sync_manager = SyncManager::new(app.handle().clone(), run_log.clone());
+            let saved = sync_manager.clone();
+            tauri::async_runtime::spawn(async move {
+                saved.restore_keep_syncs().await;
+            });
             app.manage(sync_manager);
+
+            let transfer_manager = TransferManager::new(app.handle().clone());
+            let pending = transfer_manager.clone();
+            tauri::async_runtime::spawn(async move {
+                pending.start_pending().await;
+            });
+            app.manage(transfer_manager);
+
+            let scheduler = Scheduler::new(app.handle().clone(), run_log.clone());
+            let background = scheduler.clone();
+            tauri::async_runtime::spawn(async move {
+                background.run().await;
+            });
+            app.manage(scheduler);
+            app.manage(run_log);
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
@@ -26,20 +56,52 @@ pub fn run() {
             delete_profile,
             list_buckets,
             create_bucket,
+            delete_bucket,
+            get_bucket_versioning,
+            set_bucket_versioning,
             list_objects,
+            list_object_versions,
+            restore_object_version,
+            undelete_object,
             create_folder,
             download_object,
             upload_files,
             upload_folder,
             delete_object,
             delete_objects,
+            preview_delete_prefix,
+            delete_prefix,
+            copy_object,
+            move_object,
+            rename_prefix,
+            copy_between_profiles,
             presign_url,
             sync_folder,
+            plan_sync,
+            execute_sync_plan,
+            resolve_sync_conflicts,
             get_object_content_type,
             get_object_preview,
             start_keep_sync,
             stop_keep_sync,
+            poll_keep_sync,
             get_active_syncs,
+            enqueue_uploads,
+            enqueue_folder_upload,
+            enqueue_download,
+            list_transfers,
+            pause_transfer,
+            resume_transfer,
+            cancel_transfer,
+            retry_transfer,
+            clear_finished_transfers,
+            list_sync_jobs,
+            save_sync_job,
+            delete_sync_job,
+            run_sync_job,
+            list_sync_runs,
+            get_sync_run,
+            export_sync_runs,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
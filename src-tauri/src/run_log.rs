@@ -0,0 +1,480 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::sync::{Mutex, RwLock};
+use uuid::Uuid;
+
+use crate::s3_client::{self, SyncDirection, SyncResult};
+use crate::sync::SyncAction;
+
+/// Runs kept in the log for each job; older ones are dropped. One-off
+/// syncs share a single allowance.
+const MAX_RUNS_PER_JOB: usize = 50;
+
+/// Files listed in a logged run; the rest are only counted, so a sync of a
+/// huge tree doesn't make the whole history slow to load and save.
+const MAX_FILES_PER_RUN: usize = 1000;
+
+#[derive(Error, Debug)]
+pub enum RunLogError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Failed to parse run log: {0}")]
+    Parse(#[from] serde_json::Error),
+    #[error("Sync run not found: {0}")]
+    NotFound(String),
+}
+
+pub type Result<T> = std::result::Result<T, RunLogError>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunTrigger {
+    Schedule,
+    /// A one-off sync, or a saved job run on demand.
+    Manual,
+    /// A batch of changes handled by a KeepSync.
+    KeepSync,
+}
+
+/// One sync, as kept in the run log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncRun {
+    pub id: String,
+    /// The saved job or KeepSync the run belongs to. One-off syncs have
+    /// none.
+    #[serde(default)]
+    pub job_id: Option<String>,
+    #[serde(default)]
+    pub job_name: Option<String>,
+    pub trigger: RunTrigger,
+    #[serde(default)]
+    pub profile_id: String,
+    #[serde(default)]
+    pub bucket: String,
+    #[serde(default)]
+    pub prefix: String,
+    #[serde(default)]
+    pub local_path: String,
+    /// Unset for KeepSync, which moves files both ways.
+    #[serde(default)]
+    pub direction: Option<SyncDirection>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub result: Option<SyncResult>,
+    /// Files the run moved or deleted beyond those listed in `result`.
+    #[serde(default)]
+    pub files_omitted: usize,
+    /// Why the run failed as a whole. Files that failed on their own are
+    /// listed in `result`.
+    pub error: Option<String>,
+    #[serde(default)]
+    pub error_code: Option<String>,
+}
+
+impl SyncRun {
+    /// A run starting now.
+    pub fn new(
+        trigger: RunTrigger,
+        profile_id: &str,
+        bucket: &str,
+        prefix: &str,
+        local_path: &str,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            job_id: None,
+            job_name: None,
+            trigger,
+            profile_id: profile_id.to_string(),
+            bucket: bucket.to_string(),
+            prefix: prefix.to_string(),
+            local_path: local_path.to_string(),
+            direction: None,
+            started_at: Utc::now(),
+            finished_at: None,
+            result: None,
+            files_omitted: 0,
+            error: None,
+            error_code: None,
+        }
+    }
+
+    pub fn finish(&mut self, outcome: &anyhow::Result<SyncResult>) {
+        match outcome {
+            Ok(result) => {
+                let mut result = result.clone();
+                self.files_omitted = result.files.len().saturating_sub(MAX_FILES_PER_RUN);
+                result.files.truncate(MAX_FILES_PER_RUN);
+                self.result = Some(result);
+            }
+            Err(e) => {
+                self.error = Some(format!("{:#}", e));
+                self.error_code = s3_client::s3_error_code(e);
+            }
+        }
+        self.finished_at = Some(Utc::now());
+    }
+
+    /// Whether the run, or any file in it, failed.
+    pub fn failed(&self) -> bool {
+        self.error.is_some() || self.result.as_ref().is_some_and(|r| !r.errors.is_empty())
+    }
+}
+
+/// Narrows down the runs returned from the log. Unset fields match
+/// everything.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct RunQuery {
+    pub job_id: Option<String>,
+    pub trigger: Option<RunTrigger>,
+    pub bucket: Option<String>,
+    /// Only runs that started at or after this time.
+    pub since: Option<DateTime<Utc>>,
+    /// Only runs that failed or had files fail.
+    pub failed_only: bool,
+    pub limit: Option<usize>,
+}
+
+impl RunQuery {
+    fn matches(&self, run: &SyncRun) -> bool {
+        self.job_id
+            .as_ref()
+            .map_or(true, |id| run.job_id.as_ref() == Some(id))
+            && self.trigger.map_or(true, |trigger| run.trigger == trigger)
+            && self
+                .bucket
+                .as_ref()
+                .map_or(true, |bucket| &run.bucket == bucket)
+            && self.since.map_or(true, |since| run.started_at >= since)
+            && (!self.failed_only || run.failed())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportFormat {
+    Json,
+    Csv,
+}
+
+pub fn get_log_path() -> PathBuf {
+    let home = dirs::home_dir().expect("Failed to get home directory");
+    home.join(".s3gui").join("sync-history.json")
+}
+
+fn load_runs() -> Result<Vec<SyncRun>> {
+    let path = get_log_path();
+
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let content = fs::read_to_string(&path)?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+async fn save_runs(content: String) -> Result<()> {
+    let path = get_log_path();
+
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+
+    tokio::fs::write(&path, content).await?;
+    Ok(())
+}
+
+/// Drops all but the newest `max_per_job` runs of each job. Runs are kept
+/// oldest first.
+fn prune_runs(runs: &mut Vec<SyncRun>, max_per_job: usize) {
+    let mut kept: HashMap<Option<String>, usize> = HashMap::new();
+    let mut keep = vec![false; runs.len()];
+    for (index, run) in runs.iter().enumerate().rev() {
+        let count = kept.entry(run.job_id.clone()).or_insert(0);
+        if *count < max_per_job {
+            *count += 1;
+            keep[index] = true;
+        }
+    }
+
+    let mut keep = keep.into_iter();
+    runs.retain(|_| keep.next().unwrap_or(false));
+}
+
+/// Every sync run, whether one-off, scheduled or KeepSync, kept in
+/// `~/.s3gui/sync-history.json`.
+#[derive(Clone)]
+pub struct RunLog {
+    runs: Arc<RwLock<Vec<SyncRun>>>,
+    /// Held while the history is written, so saves land in the order their
+    /// snapshots were taken.
+    saving: Arc<Mutex<()>>,
+}
+
+impl RunLog {
+    pub fn load() -> Self {
+        Self {
+            runs: Arc::new(RwLock::new(load_runs().unwrap_or_default())),
+            saving: Arc::new(Mutex::new(())),
+        }
+    }
+
+    pub async fn record(&self, run: SyncRun) {
+        let _saving = self.saving.lock().await;
+        let content = {
+            let mut runs = self.runs.write().await;
+            runs.push(run);
+            prune_runs(&mut runs, MAX_RUNS_PER_JOB);
+            serde_json::to_string_pretty(&*runs)
+        };
+        if let Ok(content) = content {
+            let _ = save_runs(content).await;
+        }
+    }
+
+    /// Runs matching `query`, newest first.
+    pub async fn runs(&self, query: &RunQuery) -> Vec<SyncRun> {
+        self.runs
+            .read()
+            .await
+            .iter()
+            .rev()
+            .filter(|run| query.matches(run))
+            .take(query.limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect()
+    }
+
+    pub async fn run(&self, run_id: &str) -> Result<SyncRun> {
+        self.runs
+            .read()
+            .await
+            .iter()
+            .find(|run| run.id == run_id)
+            .cloned()
+            .ok_or_else(|| RunLogError::NotFound(run_id.to_string()))
+    }
+
+    /// Writes the given runs to `path` as a JSON array, or as CSV with a
+    /// row per file.
+    pub async fn export(&self, run_ids: &[String], format: ReportFormat, path: &str) -> Result<()> {
+        let mut runs = Vec::new();
+        for run_id in run_ids {
+            runs.push(self.run(run_id).await?);
+        }
+
+        let content = match format {
+            ReportFormat::Json => serde_json::to_string_pretty(&runs)?,
+            ReportFormat::Csv => runs_to_csv(&runs),
+        };
+        fs::write(path, content)?;
+        Ok(())
+    }
+}
+
+const CSV_HEADER: [&str; 13] = [
+    "run_id",
+    "trigger",
+    "job",
+    "bucket",
+    "prefix",
+    "local_path",
+    "started_at",
+    "finished_at",
+    "path",
+    "action",
+    "size",
+    "error_code",
+    "error",
+];
+
+/// One row per file moved or deleted and one per file that failed. A run
+/// with neither still gets a row, carrying its own error if it had one.
+fn runs_to_csv(runs: &[SyncRun]) -> String {
+    let mut csv = csv_line(CSV_HEADER.iter().map(|s| s.to_string()));
+
+    for run in runs {
+        let trigger = match run.trigger {
+            RunTrigger::Schedule => "schedule",
+            RunTrigger::Manual => "manual",
+            RunTrigger::KeepSync => "keep_sync",
+        };
+        let fields = |path: &str, action: &str, size: String, code: &str, error: &str| {
+            csv_line(
+                [
+                    run.id.as_str(),
+                    trigger,
+                    run.job_name.as_deref().unwrap_or_default(),
+                    &run.bucket,
+                    &run.prefix,
+                    &run.local_path,
+                    &run.started_at.to_rfc3339(),
+                    &run.finished_at.map(|t| t.to_rfc3339()).unwrap_or_default(),
+                    path,
+                    action,
+                    &size,
+                    code,
+                    error,
+                ]
+                .iter()
+                .map(|s| s.to_string()),
+            )
+        };
+
+        let mut rows = 0;
+        if let Some(result) = &run.result {
+            for file in &result.files {
+                csv += &fields(
+                    &file.path,
+                    action_name(file.action),
+                    file.size.to_string(),
+                    "",
+                    "",
+                );
+                rows += 1;
+            }
+            for error in &result.errors {
+                csv += &fields(
+                    &error.path,
+                    "failed",
+                    String::new(),
+                    error.code.as_deref().unwrap_or_default(),
+                    &error.message,
+                );
+                rows += 1;
+            }
+        }
+        if rows == 0 {
+            csv += &fields(
+                "",
+                "",
+                String::new(),
+                run.error_code.as_deref().unwrap_or_default(),
+                run.error.as_deref().unwrap_or_default(),
+            );
+        }
+    }
+
+    csv
+}
+
+fn action_name(action: SyncAction) -> &'static str {
+    match action {
+        SyncAction::Skip => "skip",
+        SyncAction::Upload => "upload",
+        SyncAction::Download => "download",
+        SyncAction::DeleteLocal => "delete_local",
+        SyncAction::DeleteRemote => "delete_remote",
+        SyncAction::Conflict => "conflict",
+    }
+}
+
+fn csv_line(fields: impl Iterator<Item = String>) -> String {
+    let fields: Vec<String> = fields
+        .map(|field| {
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field
+            }
+        })
+        .collect();
+    format!("{}\r\n", fields.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::s3_client::{SyncFileError, SyncedFile};
+
+    fn run(job_id: &str) -> SyncRun {
+        let mut run = SyncRun::new(RunTrigger::Manual, "profile", "bucket", "", "/data");
+        run.job_id = Some(job_id.to_string());
+        run
+    }
+
+    #[test]
+    fn test_prune_runs_keeps_newest_runs_per_job() {
+        let mut runs = vec![run("a"), run("b"), run("a"), run("a")];
+        let newest_a = runs[3].id.clone();
+        let previous_a = runs[2].id.clone();
+
+        prune_runs(&mut runs, 2);
+
+        let ids: Vec<&str> = runs.iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(runs.len(), 3);
+        assert_eq!(runs[0].job_id.as_deref(), Some("b"));
+        assert_eq!(ids[1..], [previous_a.as_str(), newest_a.as_str()]);
+    }
+
+    #[test]
+    fn test_failed_only_query_matches_file_errors() {
+        let clean = run("a");
+        let mut partial = run("a");
+        partial.result = Some(SyncResult {
+            errors: vec![SyncFileError {
+                path: "a.txt".to_string(),
+                message: "Access Denied".to_string(),
+                code: Some("AccessDenied".to_string()),
+            }],
+            ..SyncResult::default()
+        });
+
+        let query = RunQuery {
+            failed_only: true,
+            ..RunQuery::default()
+        };
+        assert!(!query.matches(&clean));
+        assert!(query.matches(&partial));
+    }
+
+    #[test]
+    fn test_finish_caps_the_files_listed() {
+        let file = SyncedFile {
+            path: "a.txt".to_string(),
+            action: SyncAction::Upload,
+            size: 1,
+        };
+        let result = SyncResult {
+            files: vec![file; MAX_FILES_PER_RUN + 5],
+            ..SyncResult::default()
+        };
+
+        let mut run = run("a");
+        run.finish(&Ok(result));
+
+        assert_eq!(run.result.unwrap().files.len(), MAX_FILES_PER_RUN);
+        assert_eq!(run.files_omitted, 5);
+    }
+
+    #[test]
+    fn test_csv_has_a_row_per_file_and_error() {
+        let mut run = run("a");
+        run.result = Some(SyncResult {
+            files: vec![SyncedFile {
+                path: "notes, draft.txt".to_string(),
+                action: SyncAction::Upload,
+                size: 12,
+            }],
+            errors: vec![SyncFileError {
+                path: "secret.txt".to_string(),
+                message: "Access \"Denied\"".to_string(),
+                code: Some("AccessDenied".to_string()),
+            }],
+            ..SyncResult::default()
+        });
+
+        let csv = runs_to_csv(&[run]);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].contains(",\"notes, draft.txt\",upload,12,,"));
+        assert!(lines[2].ends_with(",secret.txt,failed,,AccessDenied,\"Access \"\"Denied\"\"\""));
+    }
+}
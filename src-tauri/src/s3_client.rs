@@ -1,22 +1,42 @@
-use std::path::Path;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::time::Duration;
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use aws_config::BehaviorVersion;
 use aws_credential_types::Credentials;
 use aws_sdk_s3::config::Region;
+use aws_sdk_s3::operation::head_object::HeadObjectOutput;
 use aws_sdk_s3::presigning::PresigningConfig;
-use aws_sdk_s3::primitives::ByteStream;
-use aws_sdk_s3::types::{BucketCannedAcl, ObjectCannedAcl};
+use aws_sdk_s3::primitives::{ByteStream, Length};
+use aws_sdk_s3::types::{
+    BucketCannedAcl, BucketVersioningStatus, CompletedMultipartUpload, CompletedPart,
+    MetadataDirective, ObjectIdentifier, ServerSideEncryption, TaggingDirective,
+    VersioningConfiguration,
+};
+use bytes::Bytes;
 use chrono::{DateTime, Utc};
+use filetime::FileTime;
+use futures::stream::{self, FuturesUnordered, StreamExt};
+use futures::FutureExt;
 use md5::{Digest, Md5};
 use serde::{Deserialize, Serialize};
 use tokio::fs::File;
-use tokio::io::AsyncReadExt;
-use walkdir::WalkDir;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufWriter};
+
+use crate::filter::{FilterRules, PathFilter, SymlinkPolicy};
+use crate::hash_cache::{self, FileStat};
+use crate::sync::{
+    candidate_part_sizes, compare_checksums, conflict_copy_name, multipart_part_count, CompareMode,
+    ConflictPolicy, ConflictResolution, RemoteMtime, SyncAction, SyncConflict, SyncEntry,
+};
+use crate::sync_db::{SyncDatabase, SyncRecord};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Profile {
+    pub id: String,
     pub name: String,
     pub access_key_id: String,
     pub secret_access_key: String,
@@ -38,6 +58,128 @@ impl Default for SignatureVersion {
     }
 }
 
+/// S3 rejects multipart uploads with more parts than this.
+const MAX_UPLOAD_PARTS: u64 = 10_000;
+/// Smallest part size S3 accepts for every part except the last.
+const MIN_PART_SIZE: u64 = 5 * 1024 * 1024;
+/// Upper bound on how much of a download is buffered before hitting disk.
+const DOWNLOAD_BUFFER_SIZE: usize = 1024 * 1024;
+/// How often the resume marker of an in-flight download is brought up to date.
+const RESUME_CHECKPOINT_BYTES: u64 = 8 * 1024 * 1024;
+/// User metadata recording the part size of a multipart upload, so its
+/// ETag can be reproduced from the local file later.
+const PART_SIZE_METADATA: &str = "s3gui-part-size";
+/// User metadata holding the file's modification time at upload, as Unix
+/// seconds with a fractional part (the format rclone uses).
+const MTIME_METADATA: &str = "mtime";
+/// User metadata holding the Unix mode (in octal), owner and group of an
+/// uploaded file, under the names rclone uses.
+const MODE_METADATA: &str = "mode";
+const UID_METADATA: &str = "uid";
+const GID_METADATA: &str = "gid";
+/// User metadata marking an object whose body is the target of a symlink.
+const SYMLINK_METADATA: &str = "s3gui-symlink";
+/// Most keys a single DeleteObjects request accepts.
+const DELETE_BATCH_SIZE: usize = 1000;
+/// DeleteObjects requests sent at the same time by a large deletion.
+const DELETE_BATCH_CONCURRENCY: usize = 4;
+/// Largest object a single CopyObject request can copy; anything bigger is
+/// copied part by part with UploadPartCopy.
+const MAX_COPY_OBJECT_SIZE: u64 = 5 * 1024 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct MultipartConfig {
+    /// Files at or above this size are uploaded with the multipart API.
+    pub threshold: u64,
+    pub part_size: u64,
+    /// Number of parts uploaded in parallel.
+    pub concurrency: usize,
+    /// How many times a failed part is retried before the upload is aborted.
+    pub max_retries: u32,
+}
+
+impl Default for MultipartConfig {
+    fn default() -> Self {
+        Self {
+            threshold: 64 * 1024 * 1024,
+            part_size: 16 * 1024 * 1024,
+            concurrency: 4,
+            max_retries: 3,
+        }
+    }
+}
+
+/// How many files a sync works on at once.
+#[derive(Debug, Clone, Copy)]
+pub struct SyncConcurrency {
+    /// Files hashed and compared in parallel while planning.
+    pub hashing: usize,
+    /// Files uploaded or downloaded in parallel.
+    pub transfers: usize,
+}
+
+impl Default for SyncConcurrency {
+    fn default() -> Self {
+        Self {
+            hashing: 8,
+            transfers: 4,
+        }
+    }
+}
+
+impl MultipartConfig {
+    /// Picks a part size that respects the S3 minimum and keeps the part
+    /// count within `MAX_UPLOAD_PARTS` for a file of `file_size` bytes.
+    pub fn part_size_for(&self, file_size: u64) -> u64 {
+        let min_for_count = (file_size + MAX_UPLOAD_PARTS - 1) / MAX_UPLOAD_PARTS;
+        self.part_size.max(MIN_PART_SIZE).max(min_for_count)
+    }
+}
+
+/// Shared between a running transfer and whoever is watching it. Progress is
+/// read from here, and setting the cancel flag makes the transfer stop at the
+/// next chunk or part boundary.
+#[derive(Debug, Default)]
+pub struct TransferControl {
+    transferred: AtomicU64,
+    total: AtomicU64,
+    cancelled: AtomicBool,
+}
+
+impl TransferControl {
+    pub fn transferred(&self) -> u64 {
+        self.transferred.load(Ordering::Relaxed)
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total.load(Ordering::Relaxed)
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    fn start(&self, transferred: u64, total: u64) {
+        self.transferred.store(transferred, Ordering::Relaxed);
+        self.total.store(total, Ordering::Relaxed);
+    }
+
+    fn advance(&self, bytes: u64) {
+        self.transferred.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    fn check_cancelled(&self) -> Result<()> {
+        if self.is_cancelled() {
+            bail!("Transfer cancelled");
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BucketInfo {
     pub name: String,
@@ -61,6 +203,38 @@ pub struct ListObjectsResult {
     pub is_truncated: bool,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BucketVersioning {
+    /// Versioning has never been turned on.
+    Disabled,
+    Enabled,
+    /// Versioning was turned off again. Existing versions are kept, but new
+    /// writes replace the current object.
+    Suspended,
+}
+
+/// One version of an object, or a delete marker hiding older versions.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ObjectVersion {
+    pub key: String,
+    pub version_id: String,
+    pub is_latest: bool,
+    pub is_delete_marker: bool,
+    pub size: i64,
+    pub last_modified: Option<DateTime<Utc>>,
+    pub etag: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListVersionsResult {
+    pub versions: Vec<ObjectVersion>,
+    pub common_prefixes: Vec<String>,
+    pub next_key_marker: Option<String>,
+    pub next_version_id_marker: Option<String>,
+    pub is_truncated: bool,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ObjectMetadata {
     pub content_type: Option<String>,
@@ -70,9 +244,47 @@ pub struct ObjectMetadata {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum SyncDirection {
     LocalToRemote,
     RemoteToLocal,
+    /// Propagates changes both ways using the state saved by the last run.
+    Bidirectional,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyncOptions {
+    /// Only consulted by bidirectional sync, the one direction that can
+    /// see both sides change.
+    pub conflict_policy: ConflictPolicy,
+    /// Applied to local paths and, relative to the prefix, to keys.
+    pub filters: FilterRules,
+    pub compare_mode: CompareMode,
+    /// Mirror mode for one-way syncs: remove files from the destination
+    /// that no longer exist in the source.
+    pub delete: bool,
+    /// Refuse to mirror when it would delete more than this percentage of
+    /// the files at the destination.
+    pub max_delete_percent: u8,
+    pub symlinks: SymlinkPolicy,
+    /// Save the Unix mode, owner and group of uploaded files and restore
+    /// them on download. Modification times are always kept.
+    pub preserve_permissions: bool,
+}
+
+impl Default for SyncOptions {
+    fn default() -> Self {
+        Self {
+            conflict_policy: ConflictPolicy::default(),
+            filters: FilterRules::default(),
+            compare_mode: CompareMode::default(),
+            delete: false,
+            max_delete_percent: 50,
+            symlinks: SymlinkPolicy::default(),
+            preserve_permissions: false,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -81,12 +293,202 @@ pub struct SyncResult {
     pub downloaded: u64,
     pub deleted: u64,
     pub skipped: u64,
+    /// Paths that changed on both sides. Entries without a resolution were
+    /// left untouched for the user to decide.
+    #[serde(default)]
+    pub conflicts: Vec<SyncConflict>,
+    /// Files that couldn't be checked or transferred.
+    #[serde(default)]
+    pub errors: Vec<SyncFileError>,
+    /// Bytes uploaded and downloaded.
+    #[serde(default)]
+    pub bytes_transferred: u64,
+    /// Every file uploaded, downloaded or deleted, in the order it finished.
+    #[serde(default)]
+    pub files: Vec<SyncedFile>,
+}
+
+impl SyncResult {
+    /// Counts a finished action on `path`. Transfers and deletes are also
+    /// listed in `files`.
+    pub fn record(&mut self, path: &str, action: SyncAction, size: u64) {
+        match action {
+            SyncAction::Upload => self.uploaded += 1,
+            SyncAction::Download => self.downloaded += 1,
+            SyncAction::DeleteLocal | SyncAction::DeleteRemote => self.deleted += 1,
+            SyncAction::Skip => {
+                self.skipped += 1;
+                return;
+            }
+            SyncAction::Conflict => return,
+        }
+        if matches!(action, SyncAction::Upload | SyncAction::Download) {
+            self.bytes_transferred += size;
+        }
+        self.files.push(SyncedFile {
+            path: path.to_string(),
+            action,
+            size,
+        });
+    }
+}
+
+/// A file a sync uploaded, downloaded or deleted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncedFile {
+    pub path: String,
+    pub action: SyncAction,
+    pub size: u64,
+}
+
+/// A file a sync gave up on. The rest of the sync carries on without it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncFileError {
+    pub path: String,
+    pub message: String,
+    /// The S3 error code, when S3 rejected a request for the file.
+    #[serde(default)]
+    pub code: Option<String>,
+}
+
+impl SyncFileError {
+    pub fn new(path: &str, error: &anyhow::Error) -> Self {
+        Self {
+            path: path.to_string(),
+            message: format!("{:#}", error),
+            code: s3_error_code(error),
+        }
+    }
+}
+
+/// The S3 error code (`AccessDenied`, `NoSuchBucket`, ...) behind `error`,
+/// if it came from a request S3 turned down.
+pub fn s3_error_code(error: &anyhow::Error) -> Option<String> {
+    use aws_sdk_s3::error::{ProvideErrorMetadata, SdkError};
+    use aws_sdk_s3::operation::{
+        complete_multipart_upload::CompleteMultipartUploadError, copy_object::CopyObjectError,
+        create_multipart_upload::CreateMultipartUploadError, delete_object::DeleteObjectError,
+        delete_objects::DeleteObjectsError, get_object::GetObjectError,
+        get_object_tagging::GetObjectTaggingError, head_object::HeadObjectError,
+        list_object_versions::ListObjectVersionsError, list_objects_v2::ListObjectsV2Error,
+        put_object::PutObjectError, upload_part::UploadPartError,
+        upload_part_copy::UploadPartCopyError,
+    };
+
+    fn code<E>(cause: &(dyn std::error::Error + 'static)) -> Option<String>
+    where
+        E: ProvideErrorMetadata + std::error::Error + 'static,
+    {
+        cause
+            .downcast_ref::<SdkError<E>>()?
+            .code()
+            .map(str::to_string)
+    }
+
+    error.chain().find_map(|cause| {
+        code::<GetObjectError>(cause)
+            .or_else(|| code::<PutObjectError>(cause))
+            .or_else(|| code::<HeadObjectError>(cause))
+            .or_else(|| code::<ListObjectsV2Error>(cause))
+            .or_else(|| code::<ListObjectVersionsError>(cause))
+            .or_else(|| code::<DeleteObjectError>(cause))
+            .or_else(|| code::<DeleteObjectsError>(cause))
+            .or_else(|| code::<CreateMultipartUploadError>(cause))
+            .or_else(|| code::<UploadPartError>(cause))
+            .or_else(|| code::<CompleteMultipartUploadError>(cause))
+            .or_else(|| code::<CopyObjectError>(cause))
+            .or_else(|| code::<UploadPartCopyError>(cause))
+            .or_else(|| code::<GetObjectTaggingError>(cause))
+    })
+}
+
+/// What a sync will do, worked out by `plan_sync` before anything changes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncPlan {
+    pub bucket: String,
+    pub prefix: String,
+    pub local_path: String,
+    pub direction: SyncDirection,
+    pub actions: Vec<PlannedAction>,
+    /// Files that couldn't be compared and are left out of the plan.
+    #[serde(default)]
+    pub errors: Vec<SyncFileError>,
+    /// Taken from the options the plan was made with, since carrying it
+    /// out depends on them too.
+    #[serde(default)]
+    pub symlinks: SymlinkPolicy,
+    #[serde(default)]
+    pub preserve_permissions: bool,
+}
+
+/// What a transfer carries across besides the contents of a file.
+#[derive(Debug, Clone, Default)]
+struct TransferAttributes {
+    /// Symlinks become link objects and link objects become symlinks.
+    links: bool,
+    permissions: bool,
+    /// Sync folder a download has to stay inside, since its path comes
+    /// from a key.
+    root: Option<PathBuf>,
+}
+
+impl TransferAttributes {
+    fn of(plan: &SyncPlan) -> Self {
+        Self {
+            links: plan.symlinks == SymlinkPolicy::Store,
+            permissions: plan.preserve_permissions,
+            root: Some(PathBuf::from(&plan.local_path)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlannedAction {
+    /// Path relative to both the local folder and the prefix.
+    pub path: String,
+    pub action: SyncAction,
+    /// Size of the file being transferred, deleted or skipped.
+    pub size: u64,
+    pub reason: String,
+    pub local_md5: Option<String>,
+    pub remote_etag: Option<String>,
+    /// Set for conflicts, with the resolution the policy picked if any.
+    #[serde(default)]
+    pub conflict: Option<SyncConflict>,
+}
+
+impl PlannedAction {
+    fn new(path: &str, action: SyncAction, size: u64, reason: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            action,
+            size,
+            reason: reason.to_string(),
+            local_md5: None,
+            remote_etag: None,
+            conflict: None,
+        }
+    }
+
+    fn from_entry(entry: &SyncEntry, size: u64) -> Self {
+        Self {
+            path: entry.path.clone(),
+            action: entry.action,
+            size,
+            reason: entry.reason().to_string(),
+            local_md5: entry.local_md5.clone(),
+            remote_etag: entry.remote_etag.clone(),
+            conflict: None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DeleteError {
     pub key: String,
     pub message: String,
+    #[serde(default)]
+    pub code: Option<String>,
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -95,18 +497,85 @@ pub struct DeleteObjectsResult {
     pub errors: Vec<DeleteError>,
 }
 
+/// Changes made to an object while it is copied. Anything left unset is
+/// carried over from the source object.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CopyOverrides {
+    pub content_type: Option<String>,
+    pub metadata: Option<HashMap<String, String>>,
+    pub tags: Option<HashMap<String, String>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ObjectError {
+    pub key: String,
+    pub message: String,
+    #[serde(default)]
+    pub code: Option<String>,
+}
+
+impl ObjectError {
+    pub fn new(key: &str, error: &anyhow::Error) -> Self {
+        Self {
+            key: key.to_string(),
+            message: format!("{:#}", error),
+            code: s3_error_code(error),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PrefixSummary {
+    pub objects: u64,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RenamePrefixResult {
+    pub moved: u64,
+    pub errors: Vec<ObjectError>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CopyPrefixResult {
+    pub copied: u64,
+    pub errors: Vec<ObjectError>,
+}
+
 pub struct S3Client {
     client: aws_sdk_s3::Client,
+    profile_id: String,
     region: String,
+    endpoint: Option<String>,
+    credentials: (String, String),
+    multipart: MultipartConfig,
+    sync_concurrency: SyncConcurrency,
 }
 
 pub struct S3ClientBuilder {
     profile: Profile,
+    multipart: MultipartConfig,
+    sync_concurrency: SyncConcurrency,
 }
 
 impl S3ClientBuilder {
     pub fn new(profile: Profile) -> Self {
-        Self { profile }
+        Self {
+            profile,
+            multipart: MultipartConfig::default(),
+            sync_concurrency: SyncConcurrency::default(),
+        }
+    }
+
+    pub fn multipart_config(mut self, multipart: MultipartConfig) -> Self {
+        self.multipart = multipart;
+        self
+    }
+
+    pub fn sync_concurrency(mut self, sync_concurrency: SyncConcurrency) -> Self {
+        self.sync_concurrency = sync_concurrency;
+        self
     }
 
     pub async fn build(self) -> Result<S3Client> {
@@ -135,12 +604,478 @@ impl S3ClientBuilder {
 
         Ok(S3Client {
             client,
+            profile_id: self.profile.id,
             region: self.profile.region,
+            endpoint: self.profile.endpoint,
+            credentials: (self.profile.access_key_id, self.profile.secret_access_key),
+            multipart: self.multipart,
+            sync_concurrency: self.sync_concurrency,
+        })
+    }
+}
+
+/// Builds a client for a saved profile, applying the transfer settings from
+/// the config file.
+pub async fn client_for_profile(profile_id: &str) -> Result<S3Client> {
+    let config = crate::config::load_config()?;
+
+    let profile = config
+        .profiles
+        .iter()
+        .find(|p| p.id == profile_id)
+        .with_context(|| format!("Profile not found: {}", profile_id))?;
+
+    let s3_profile = Profile {
+        id: profile.id.clone(),
+        name: profile.name.clone(),
+        access_key_id: profile.access_key_id.clone(),
+        secret_access_key: profile.secret_access_key.clone(),
+        region: profile.region.clone(),
+        endpoint: profile.endpoint.clone(),
+        path_style: profile.addressing_style == crate::config::AddressingStyle::Path,
+        signature_version: match profile.signature_version {
+            crate::config::SignatureVersion::V2 => SignatureVersion::V2,
+            crate::config::SignatureVersion::V4 => SignatureVersion::V4,
+        },
+    };
+
+    const MIB: u64 = 1024 * 1024;
+    let multipart = MultipartConfig {
+        threshold: config.transfer.multipart_threshold_mb * MIB,
+        part_size: config.transfer.multipart_part_size_mb * MIB,
+        concurrency: config.transfer.multipart_concurrency,
+        max_retries: config.transfer.multipart_max_retries,
+    };
+
+    let sync_concurrency = SyncConcurrency {
+        hashing: config.transfer.sync_hash_concurrency,
+        transfers: config.transfer.sync_transfer_concurrency,
+    };
+
+    S3ClientBuilder::new(s3_profile)
+        .multipart_config(multipart)
+        .sync_concurrency(sync_concurrency)
+        .build()
+        .await
+}
+
+fn object_info(obj: &aws_sdk_s3::types::Object) -> ObjectInfo {
+    let key = obj.key().unwrap_or_default().to_string();
+    let is_folder = key.ends_with('/');
+    ObjectInfo {
+        key,
+        size: obj.size().unwrap_or(0),
+        last_modified: obj
+            .last_modified()
+            .and_then(|dt| DateTime::from_timestamp(dt.secs(), dt.subsec_nanos())),
+        etag: obj.e_tag().map(|s| s.to_string()),
+        is_folder,
+    }
+}
+
+/// Guards mirror mode against wiping out the destination, e.g. when the
+/// source folder is empty because a drive isn't mounted.
+fn check_delete_cap(to_delete: usize, total: usize, max_percent: u8) -> Result<()> {
+    if to_delete > 0 && to_delete * 100 > total * usize::from(max_percent) {
+        bail!(
+            "Sync would delete {} of {} files at the destination, more than the {}% limit",
+            to_delete,
+            total,
+            max_percent
+        );
+    }
+    Ok(())
+}
+
+/// Normalises a sync prefix into the key prefix its objects live under.
+fn folder_prefix(prefix: &str) -> String {
+    let trimmed = prefix.trim_end_matches('/');
+    if trimmed.is_empty() {
+        String::new()
+    } else {
+        format!("{}/", trimmed)
+    }
+}
+
+/// Percent-encodes everything but unreserved characters, and `/` too when
+/// `keep_slash` is set.
+fn percent_encode(value: &str, keep_slash: bool) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b'/' if keep_slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Orders versions by key, newest first within each key. ListObjectVersions
+/// returns versions and delete markers as two separate lists.
+fn sort_versions(versions: &mut [ObjectVersion]) {
+    versions.sort_by(|a, b| {
+        a.key
+            .cmp(&b.key)
+            .then_with(|| b.is_latest.cmp(&a.is_latest))
+            .then_with(|| b.last_modified.cmp(&a.last_modified))
+    });
+}
+
+/// DeleteObjects entries for the current version of each key.
+fn object_identifiers(keys: &[String]) -> Vec<ObjectIdentifier> {
+    keys.iter()
+        .filter_map(|key| ObjectIdentifier::builder().key(key).build().ok())
+        .collect()
+}
+
+/// Runs `delete` over `objects` in batches of `DELETE_BATCH_SIZE`, a few at
+/// a time. A batch the service turns down as a whole is reported as an
+/// error for each of its keys.
+async fn delete_each<'a, F, Fut>(
+    objects: &'a [ObjectIdentifier],
+    progress: impl Fn(u64, u64) + Send + Sync,
+    delete: F,
+) -> DeleteObjectsResult
+where
+    F: Fn(&'a [ObjectIdentifier]) -> Fut,
+    Fut: Future<Output = Result<DeleteObjectsResult>> + Send + 'a,
+{
+    let total = objects.len() as u64;
+    let done = AtomicU64::new(0);
+
+    let batches: Vec<_> = objects
+        .chunks(DELETE_BATCH_SIZE)
+        .map(|batch| {
+            let (done, progress) = (&done, &progress);
+            let deleted = delete(batch);
+            async move {
+                let result = match deleted.await {
+                    Ok(result) => result,
+                    Err(e) => DeleteObjectsResult {
+                        deleted: 0,
+                        errors: batch
+                            .iter()
+                            .map(|object| DeleteError {
+                                key: object.key().to_string(),
+                                message: format!("{:#}", e),
+                                code: s3_error_code(&e),
+                            })
+                            .collect(),
+                    },
+                };
+                let count = batch.len() as u64;
+                progress(done.fetch_add(count, Ordering::Relaxed) + count, total);
+                result
+            }
+            .boxed()
+        })
+        .collect();
+
+    let results: Vec<DeleteObjectsResult> = stream::iter(batches)
+        .buffer_unordered(DELETE_BATCH_CONCURRENCY)
+        .collect()
+        .await;
+
+    let mut combined = DeleteObjectsResult::default();
+    for result in results {
+        combined.deleted += result.deleted;
+        combined.errors.extend(result.errors);
+    }
+    combined
+}
+
+/// The `x-amz-copy-source` value for an object: `bucket/key` with the key
+/// URL-encoded, plus `?versionId=` when copying an older version.
+fn copy_source(bucket: &str, key: &str, version_id: Option<&str>) -> String {
+    let source = format!("{}/{}", bucket, percent_encode(key, true));
+    match version_id {
+        Some(version_id) => format!("{}?versionId={}", source, percent_encode(version_id, false)),
+        None => source,
+    }
+}
+
+/// Tags in the URL query form the `x-amz-tagging` header expects.
+fn tagging_query(tags: &HashMap<String, String>) -> String {
+    let sorted: BTreeMap<_, _> = tags.iter().collect();
+    sorted
+        .into_iter()
+        .map(|(key, value)| {
+            format!(
+                "{}={}",
+                percent_encode(key, false),
+                percent_encode(value, false)
+            )
         })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+async fn file_size(path: &Path) -> Result<u64> {
+    Ok(tokio::fs::metadata(path)
+        .await
+        .context("Failed to read file metadata")?
+        .len())
+}
+
+/// User metadata attached to every uploaded file. The Unix mode, owner
+/// and group are only included when `permissions` is set.
+fn upload_metadata(metadata: &std::fs::Metadata, permissions: bool) -> HashMap<String, String> {
+    let mut user_metadata = HashMap::new();
+    if let Ok(modified) = metadata.modified() {
+        let modified = DateTime::<Utc>::from(modified);
+        user_metadata.insert(
+            MTIME_METADATA.to_string(),
+            format!(
+                "{}.{:03}",
+                modified.timestamp(),
+                modified.timestamp_subsec_millis()
+            ),
+        );
+    }
+    if permissions {
+        user_metadata.extend(permission_metadata(metadata));
+    }
+    user_metadata
+}
+
+#[cfg(unix)]
+fn permission_metadata(metadata: &std::fs::Metadata) -> Vec<(String, String)> {
+    use std::os::unix::fs::MetadataExt;
+
+    vec![
+        (MODE_METADATA.to_string(), format!("0{:o}", metadata.mode())),
+        (UID_METADATA.to_string(), metadata.uid().to_string()),
+        (GID_METADATA.to_string(), metadata.gid().to_string()),
+    ]
+}
+
+#[cfg(not(unix))]
+fn permission_metadata(_metadata: &std::fs::Metadata) -> Vec<(String, String)> {
+    Vec::new()
+}
+
+/// Applies the mode, owner and group saved by `upload_metadata`. Only root
+/// can give files away, so ownership is restored where the OS allows it.
+#[cfg(unix)]
+fn restore_permissions(path: &Path, metadata: &HashMap<String, String>) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let number = |key: &str, radix: u32| {
+        metadata
+            .get(key)
+            .and_then(|value| u32::from_str_radix(value.trim(), radix).ok())
+    };
+
+    if let (Some(uid), Some(gid)) = (number(UID_METADATA, 10), number(GID_METADATA, 10)) {
+        let _ = chown(path, uid, gid);
+    }
+    // Set after chown, which clears the setuid and setgid bits.
+    if let Some(mode) = number(MODE_METADATA, 8) {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode & 0o7777))
+            .context("Failed to restore file mode")?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn restore_permissions(_path: &Path, _metadata: &HashMap<String, String>) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn chown(path: &Path, uid: u32, gid: u32) -> std::io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes())?;
+    // SAFETY: `path` is a NUL-terminated string that outlives the call.
+    if unsafe { libc::chown(path.as_ptr(), uid, gid) } == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+/// Where `path` points, if it's a symlink that a sync with `symlinks`
+/// stores as a link object.
+fn stored_link(path: &Path, symlinks: SymlinkPolicy) -> Option<PathBuf> {
+    if symlinks != SymlinkPolicy::Store {
+        return None;
+    }
+    std::fs::read_link(path).ok()
+}
+
+/// The body of the link object for a symlink to `target`.
+#[cfg(unix)]
+fn link_body(target: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    target.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn link_body(target: &Path) -> Vec<u8> {
+    target.to_string_lossy().into_owned().into_bytes()
+}
+
+fn is_link_object(metadata: Option<&HashMap<String, String>>) -> bool {
+    metadata.is_some_and(|m| m.contains_key(SYMLINK_METADATA))
+}
+
+/// Fails unless writing `path` stays inside `root`: no `..` climbing out
+/// of it and no symlinked directory on the way leading somewhere else.
+pub async fn ensure_inside(root: &Path, path: &Path) -> Result<()> {
+    let outside = || {
+        anyhow!(
+            "Refusing to write outside the sync folder: {}",
+            path.display()
+        )
+    };
+
+    let relative = path.strip_prefix(root).map_err(|_| outside())?;
+    if !relative
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(outside());
+    }
+
+    let root = tokio::fs::canonicalize(root)
+        .await
+        .context("Failed to resolve sync folder")?;
+    let mut existing = path.parent();
+    while let Some(dir) = existing {
+        if tokio::fs::symlink_metadata(dir).await.is_ok() {
+            let dir = tokio::fs::canonicalize(dir)
+                .await
+                .context("Failed to resolve download folder")?;
+            if !dir.starts_with(&root) {
+                return Err(outside());
+            }
+            break;
+        }
+        existing = dir.parent();
+    }
+    Ok(())
+}
+
+/// Whether a symlink at `path` pointing to `target` resolves to somewhere
+/// inside `root`. Absolute targets never do.
+fn link_stays_inside(root: &Path, path: &Path, target: &Path) -> bool {
+    let Some(dir) = path.parent().and_then(|dir| dir.strip_prefix(root).ok()) else {
+        return false;
+    };
+
+    let mut depth: Vec<Component> = dir.components().collect();
+    for component in target.components() {
+        match component {
+            Component::Normal(_) => depth.push(component),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if depth.pop().is_none() {
+                    return false;
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return false,
+        }
+    }
+    true
+}
+
+/// Replaces whatever file or link is at `path` with a symlink to the
+/// target held in a link object's body. With a `root`, targets leading
+/// out of it are refused.
+#[cfg(unix)]
+async fn replace_with_symlink(path: &Path, body: &[u8], root: Option<&Path>) -> Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let target = Path::new(std::ffi::OsStr::from_bytes(body));
+    if root.is_some_and(|root| !link_stays_inside(root, path, target)) {
+        bail!(
+            "Refusing to create a symlink leading outside the sync folder: {}",
+            target.display()
+        );
+    }
+    if tokio::fs::symlink_metadata(path).await.is_ok() {
+        tokio::fs::remove_file(path)
+            .await
+            .context("Failed to replace local file with a symlink")?;
+    }
+    tokio::fs::symlink(target, path)
+        .await
+        .context("Failed to create symlink")
+}
+
+#[cfg(not(unix))]
+async fn replace_with_symlink(_path: &Path, _body: &[u8], _root: Option<&Path>) -> Result<()> {
+    bail!("Symlinks can only be restored on Unix")
+}
+
+/// Plans a symlink stored as a link object. The two sides match when the
+/// object holds the link's target, which its ETag shows without a request.
+fn plan_link(
+    path: &str,
+    target: &Path,
+    remote: Option<&ObjectInfo>,
+    transfer: SyncAction,
+) -> PlannedAction {
+    let body = link_body(target);
+    let md5 = format!("{:x}", Md5::digest(&body));
+
+    let (action, reason) = match remote {
+        None => (transfer, "Missing on the remote"),
+        Some(obj)
+            if obj
+                .etag
+                .as_deref()
+                .is_some_and(|etag| compare_checksums(&md5, etag)) =>
+        {
+            (SyncAction::Skip, "Unchanged")
+        }
+        Some(_) => (transfer, "Link target differs"),
+    };
+    let size = match (action, remote) {
+        (SyncAction::Download, Some(obj)) => obj.size.max(0) as u64,
+        _ => body.len() as u64,
+    };
+
+    let mut planned = PlannedAction::new(path, action, size, reason);
+    planned.local_md5 = Some(md5);
+    planned.remote_etag = remote.and_then(|o| o.etag.clone());
+    planned
+}
+
+/// The modification time stored by `upload_metadata`, if present.
+fn recorded_mtime(metadata: Option<&HashMap<String, String>>) -> Option<DateTime<Utc>> {
+    let seconds: f64 = metadata?.get(MTIME_METADATA)?.trim().parse().ok()?;
+    DateTime::from_timestamp_millis((seconds * 1000.0).round() as i64)
+}
+
+fn mtime_millis(metadata: &std::fs::Metadata) -> i64 {
+    metadata
+        .modified()
+        .ok()
+        .map(|t| DateTime::<Utc>::from(t).timestamp_millis())
+        .unwrap_or(0)
+}
+
+/// Joins a key prefix and a relative path the way uploads lay out objects.
+pub fn join_key(prefix: &str, relative: &str) -> String {
+    if prefix.is_empty() {
+        relative.to_string()
+    } else {
+        format!("{}/{}", prefix.trim_end_matches('/'), relative)
     }
 }
 
 impl S3Client {
+    /// Whether both clients reach the same service with the same keys, in
+    /// which case objects can be copied between them server-side.
+    pub fn shares_account_with(&self, other: &S3Client) -> bool {
+        self.endpoint == other.endpoint && self.credentials == other.credentials
+    }
+
     pub async fn list_buckets(&self) -> Result<Vec<BucketInfo>> {
         let resp = self
             .client
@@ -184,19 +1119,152 @@ impl S3Client {
         Ok(())
     }
 
-    pub async fn list_objects(
-        &self,
-        bucket: &str,
-        prefix: Option<&str>,
-        continuation_token: Option<&str>,
-        max_keys: i32,
-    ) -> Result<ListObjectsResult> {
-        let mut req = self
-            .client
-            .list_objects_v2()
-            .bucket(bucket)
-            .max_keys(max_keys)
-            .delimiter("/");
+    /// Deletes a bucket, which the service only allows once it is empty.
+    pub async fn delete_bucket(&self, name: &str) -> Result<()> {
+        self.client
+            .delete_bucket()
+            .bucket(name)
+            .send()
+            .await
+            .context("Failed to delete bucket")?;
+
+        Ok(())
+    }
+
+    /// Removes everything that keeps a bucket from being deleted: unfinished
+    /// multipart uploads, and every object version and delete marker. Falls
+    /// back to plain object listing on services without ListObjectVersions.
+    pub async fn empty_bucket(&self, bucket: &str) -> Result<DeleteObjectsResult> {
+        self.abort_multipart_uploads(bucket).await?;
+
+        let objects = match self.list_all_versions(bucket).await {
+            Ok(versions) => versions,
+            Err(e)
+                if matches!(
+                    s3_error_code(&e).as_deref(),
+                    Some("NotImplemented" | "MethodNotAllowed")
+                ) =>
+            {
+                let keys: Vec<String> = self
+                    .list_objects_recursive(bucket, "")
+                    .await?
+                    .into_iter()
+                    .map(|object| object.key)
+                    .collect();
+                object_identifiers(&keys)
+            }
+            Err(e) => return Err(e),
+        };
+
+        Ok(self.delete_in_batches(bucket, &objects, |_, _| {}).await)
+    }
+
+    /// Every version and delete marker in the bucket.
+    async fn list_all_versions(&self, bucket: &str) -> Result<Vec<ObjectIdentifier>> {
+        let mut objects = Vec::new();
+        let mut key_marker: Option<String> = None;
+        let mut version_id_marker: Option<String> = None;
+
+        loop {
+            let resp = self
+                .client
+                .list_object_versions()
+                .bucket(bucket)
+                .set_key_marker(key_marker.take())
+                .set_version_id_marker(version_id_marker.take())
+                .send()
+                .await
+                .context("Failed to list object versions")?;
+
+            let versions = resp
+                .versions()
+                .iter()
+                .map(|v| (v.key(), v.version_id()))
+                .chain(
+                    resp.delete_markers()
+                        .iter()
+                        .map(|m| (m.key(), m.version_id())),
+                );
+            for (key, version_id) in versions {
+                if let Some(key) = key {
+                    objects.extend(
+                        ObjectIdentifier::builder()
+                            .key(key)
+                            .set_version_id(version_id.map(str::to_string))
+                            .build()
+                            .ok(),
+                    );
+                }
+            }
+
+            if !resp.is_truncated().unwrap_or(false) {
+                break;
+            }
+            key_marker = resp.next_key_marker().map(str::to_string);
+            version_id_marker = resp.next_version_id_marker().map(str::to_string);
+            if key_marker.is_none() {
+                break;
+            }
+        }
+
+        Ok(objects)
+    }
+
+    /// Aborts every multipart upload still in progress in the bucket.
+    async fn abort_multipart_uploads(&self, bucket: &str) -> Result<()> {
+        let mut key_marker: Option<String> = None;
+        let mut upload_id_marker: Option<String> = None;
+
+        loop {
+            let resp = self
+                .client
+                .list_multipart_uploads()
+                .bucket(bucket)
+                .set_key_marker(key_marker.take())
+                .set_upload_id_marker(upload_id_marker.take())
+                .send()
+                .await
+                .context("Failed to list multipart uploads")?;
+
+            for upload in resp.uploads() {
+                if let (Some(key), Some(upload_id)) = (upload.key(), upload.upload_id()) {
+                    self.client
+                        .abort_multipart_upload()
+                        .bucket(bucket)
+                        .key(key)
+                        .upload_id(upload_id)
+                        .send()
+                        .await
+                        .with_context(|| format!("Failed to abort multipart upload of {}", key))?;
+                }
+            }
+
+            if !resp.is_truncated().unwrap_or(false) {
+                break;
+            }
+            key_marker = resp.next_key_marker().map(str::to_string);
+            upload_id_marker = resp.next_upload_id_marker().map(str::to_string);
+            if key_marker.is_none() {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn list_objects(
+        &self,
+        bucket: &str,
+        prefix: Option<&str>,
+        continuation_token: Option<&str>,
+        max_keys: i32,
+    ) -> Result<ListObjectsResult> {
+        let mut req = self
+            .client
+            .list_objects_v2()
+            .bucket(bucket)
+            .max_keys(max_keys)
+            .delimiter("/");
 
         if let Some(p) = prefix {
             req = req.prefix(p);
@@ -208,23 +1276,7 @@ impl S3Client {
 
         let resp = req.send().await.context("Failed to list objects")?;
 
-        let objects = resp
-            .contents()
-            .iter()
-            .map(|obj| {
-                let key = obj.key().unwrap_or_default().to_string();
-                let is_folder = key.ends_with('/');
-                ObjectInfo {
-                    key,
-                    size: obj.size().unwrap_or(0),
-                    last_modified: obj.last_modified().and_then(|dt| {
-                        DateTime::from_timestamp(dt.secs(), dt.subsec_nanos())
-                    }),
-                    etag: obj.e_tag().map(|s| s.to_string()),
-                    is_folder,
-                }
-            })
-            .collect();
+        let objects = resp.contents().iter().map(object_info).collect();
 
         let common_prefixes = resp
             .common_prefixes()
@@ -240,48 +1292,373 @@ impl S3Client {
         })
     }
 
-    pub async fn download_object(
+    pub async fn get_bucket_versioning(&self, bucket: &str) -> Result<BucketVersioning> {
+        let resp = self
+            .client
+            .get_bucket_versioning()
+            .bucket(bucket)
+            .send()
+            .await
+            .context("Failed to get bucket versioning")?;
+
+        Ok(match resp.status() {
+            Some(BucketVersioningStatus::Enabled) => BucketVersioning::Enabled,
+            Some(BucketVersioningStatus::Suspended) => BucketVersioning::Suspended,
+            _ => BucketVersioning::Disabled,
+        })
+    }
+
+    /// Turns versioning on, or suspends it. S3 has no way back to a bucket
+    /// that was never versioned.
+    pub async fn set_bucket_versioning(&self, bucket: &str, enabled: bool) -> Result<()> {
+        let status = if enabled {
+            BucketVersioningStatus::Enabled
+        } else {
+            BucketVersioningStatus::Suspended
+        };
+
+        self.client
+            .put_bucket_versioning()
+            .bucket(bucket)
+            .versioning_configuration(VersioningConfiguration::builder().status(status).build())
+            .send()
+            .await
+            .context("Failed to set bucket versioning")?;
+
+        Ok(())
+    }
+
+    /// Lists the versions and delete markers of the objects directly under
+    /// `prefix`, newest first for each key, like `list_objects` does for
+    /// current objects.
+    pub async fn list_object_versions(
+        &self,
+        bucket: &str,
+        prefix: Option<&str>,
+        key_marker: Option<&str>,
+        version_id_marker: Option<&str>,
+        max_keys: i32,
+    ) -> Result<ListVersionsResult> {
+        let resp = self
+            .client
+            .list_object_versions()
+            .bucket(bucket)
+            .delimiter("/")
+            .max_keys(max_keys)
+            .set_prefix(prefix.map(str::to_string))
+            .set_key_marker(key_marker.map(str::to_string))
+            .set_version_id_marker(version_id_marker.map(str::to_string))
+            .send()
+            .await
+            .context("Failed to list object versions")?;
+
+        let timestamp = |dt: Option<&aws_sdk_s3::primitives::DateTime>| {
+            dt.and_then(|dt| DateTime::from_timestamp(dt.secs(), dt.subsec_nanos()))
+        };
+        let mut versions: Vec<ObjectVersion> = resp
+            .versions()
+            .iter()
+            .map(|v| ObjectVersion {
+                key: v.key().unwrap_or_default().to_string(),
+                version_id: v.version_id().unwrap_or("null").to_string(),
+                is_latest: v.is_latest().unwrap_or(false),
+                is_delete_marker: false,
+                size: v.size().unwrap_or(0),
+                last_modified: timestamp(v.last_modified()),
+                etag: v.e_tag().map(|s| s.to_string()),
+            })
+            .chain(resp.delete_markers().iter().map(|m| ObjectVersion {
+                key: m.key().unwrap_or_default().to_string(),
+                version_id: m.version_id().unwrap_or("null").to_string(),
+                is_latest: m.is_latest().unwrap_or(false),
+                is_delete_marker: true,
+                size: 0,
+                last_modified: timestamp(m.last_modified()),
+                etag: None,
+            }))
+            .collect();
+        sort_versions(&mut versions);
+
+        let common_prefixes = resp
+            .common_prefixes()
+            .iter()
+            .filter_map(|cp| cp.prefix().map(|s| s.to_string()))
+            .collect();
+
+        Ok(ListVersionsResult {
+            versions,
+            common_prefixes,
+            next_key_marker: resp.next_key_marker().map(|s| s.to_string()),
+            next_version_id_marker: resp.next_version_id_marker().map(|s| s.to_string()),
+            is_truncated: resp.is_truncated().unwrap_or(false),
+        })
+    }
+
+    /// Makes an older version the current one again by copying it over
+    /// the object. The versions in between are kept.
+    pub async fn restore_object_version(
         &self,
         bucket: &str,
         key: &str,
-        local_path: &str,
-    ) -> Result<()> {
+        version_id: &str,
+    ) -> Result<Option<String>> {
+        self.copy_object_version(
+            bucket,
+            key,
+            Some(version_id),
+            bucket,
+            key,
+            &CopyOverrides::default(),
+        )
+        .await
+    }
+
+    /// Brings back a deleted object by removing the delete marker that
+    /// hides it, so its newest real version becomes current again.
+    pub async fn undelete_object(&self, bucket: &str, key: &str) -> Result<()> {
         let resp = self
             .client
-            .get_object()
+            .list_object_versions()
+            .bucket(bucket)
+            .prefix(key)
+            .send()
+            .await
+            .context("Failed to list object versions")?;
+
+        // A key's entries come before those of longer keys sharing the
+        // prefix, so its current delete marker is on the first page.
+        let marker = resp
+            .delete_markers()
+            .iter()
+            .find(|m| m.key() == Some(key) && m.is_latest().unwrap_or(false))
+            .and_then(|m| m.version_id())
+            .with_context(|| format!("{} isn't deleted", key))?;
+
+        self.client
+            .delete_object()
             .bucket(bucket)
             .key(key)
+            .version_id(marker)
             .send()
             .await
-            .context("Failed to get object")?;
+            .context("Failed to remove delete marker")?;
 
-        let body = resp.body.collect().await.context("Failed to read body")?;
-        let bytes = body.into_bytes();
+        Ok(())
+    }
+
+    pub async fn download_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        local_path: &str,
+    ) -> Result<()> {
+        self.download_object_tracked(bucket, key, local_path, &TransferControl::default())
+            .await
+    }
+
+    pub async fn download_object_tracked(
+        &self,
+        bucket: &str,
+        key: &str,
+        local_path: &str,
+        control: &TransferControl,
+    ) -> Result<()> {
+        self.download_object_with(
+            bucket,
+            key,
+            None,
+            local_path,
+            TransferAttributes::default(),
+            control,
+        )
+        .await
+    }
+
+    /// Downloads an object into a sync folder, refusing paths that would
+    /// end up outside `root`.
+    pub async fn download_object_within(
+        &self,
+        bucket: &str,
+        key: &str,
+        local_path: &str,
+        root: &Path,
+    ) -> Result<()> {
+        let attributes = TransferAttributes {
+            root: Some(root.to_path_buf()),
+            ..TransferAttributes::default()
+        };
+        self.download_object_with(
+            bucket,
+            key,
+            None,
+            local_path,
+            attributes,
+            &TransferControl::default(),
+        )
+        .await
+    }
+
+    /// Downloads an older version of an object.
+    pub async fn download_object_version(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: &str,
+        local_path: &str,
+    ) -> Result<()> {
+        self.download_object_with(
+            bucket,
+            key,
+            Some(version_id),
+            local_path,
+            TransferAttributes::default(),
+            &TransferControl::default(),
+        )
+        .await
+    }
 
+    async fn download_object_with(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+        local_path: &str,
+        attributes: TransferAttributes,
+        control: &TransferControl,
+    ) -> Result<()> {
         let path = Path::new(local_path);
+        if let Some(root) = &attributes.root {
+            ensure_inside(root, path).await?;
+        }
         if let Some(parent) = path.parent() {
             tokio::fs::create_dir_all(parent)
                 .await
                 .context("Failed to create parent directories")?;
         }
 
-        tokio::fs::write(local_path, bytes)
+        // Stream into a sibling temp file so an interrupted download never
+        // clobbers an existing copy of the target. The marker next to it lets
+        // a later attempt pick up where this one stopped.
+        let part_path = partial_download_path(path);
+        let marker_path = resume_marker_path(path);
+
+        let mut resumed = None;
+        if let Some(marker) = read_resume_marker(&marker_path, &part_path, bucket, key).await {
+            let ranged = self
+                .client
+                .get_object()
+                .bucket(bucket)
+                .key(key)
+                .set_version_id(version_id.map(str::to_string))
+                .range(format!("bytes={}-", marker.offset))
+                .if_match(&marker.etag)
+                .send()
+                .await;
+
+            // Any failure here (412 because the ETag moved on, 416, ...) just
+            // means the partial file is useless and we start from scratch.
+            if let Ok(resp) = ranged {
+                if resp.e_tag() == Some(marker.etag.as_str()) {
+                    let offset = if resp.content_range().is_some() {
+                        marker.offset
+                    } else {
+                        0
+                    };
+                    resumed = Some((resp, offset));
+                }
+            }
+        }
+
+        let (resp, offset) = match resumed {
+            Some(resumed) => resumed,
+            None => {
+                let resp = self
+                    .client
+                    .get_object()
+                    .bucket(bucket)
+                    .key(key)
+                    .set_version_id(version_id.map(str::to_string))
+                    .send()
+                    .await
+                    .context("Failed to get object")?;
+                (resp, 0)
+            }
+        };
+
+        let remaining = resp.content_length().unwrap_or(0).max(0) as u64;
+        control.start(offset, offset + remaining);
+
+        let modified = recorded_mtime(resp.metadata())
+            .or_else(|| {
+                resp.last_modified()
+                    .and_then(|dt| DateTime::from_timestamp(dt.secs(), dt.subsec_nanos()))
+            })
+            .map(|modified| {
+                FileTime::from_unix_time(modified.timestamp(), modified.timestamp_subsec_nanos())
+            });
+
+        if attributes.links && offset == 0 && is_link_object(resp.metadata()) {
+            let body = resp.body.collect().await.context("Failed to read body")?;
+            replace_with_symlink(path, &body.into_bytes(), attributes.root.as_deref()).await?;
+            if let Some(mtime) = modified {
+                let _ = filetime::set_symlink_file_times(path, mtime, mtime);
+            }
+            control.advance(remaining);
+            return Ok(());
+        }
+        let permissions = if attributes.permissions {
+            resp.metadata().cloned()
+        } else {
+            None
+        };
+
+        let marker = resp.e_tag().map(|etag| ResumeMarker {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            etag: etag.to_string(),
+            offset,
+        });
+        let resumable = marker.is_some();
+
+        if let Err(e) =
+            write_body_to_file(resp.body, &part_path, marker, &marker_path, control).await
+        {
+            if !resumable {
+                let _ = tokio::fs::remove_file(&part_path).await;
+            }
+            return Err(e);
+        }
+
+        tokio::fs::rename(&part_path, path)
             .await
-            .context("Failed to write file")?;
+            .context("Failed to move downloaded file into place")?;
+        let _ = tokio::fs::remove_file(&marker_path).await;
+
+        // Give the copy the source's mtime so size+mtime comparisons see
+        // the two as equal on the next sync. Not worth failing over.
+        if let Some(mtime) = modified {
+            let _ = filetime::set_file_mtime(path, mtime);
+        }
+        if let Some(permissions) = &permissions {
+            restore_permissions(path, permissions)?;
+        }
 
         Ok(())
     }
 
+    /// The body and content type of an object, or of one of its versions.
     pub async fn get_object_bytes(
         &self,
         bucket: &str,
         key: &str,
+        version_id: Option<&str>,
     ) -> Result<(Vec<u8>, Option<String>)> {
         let resp = self
             .client
             .get_object()
             .bucket(bucket)
             .key(key)
+            .set_version_id(version_id.map(str::to_string))
             .send()
             .await
             .context("Failed to get object")?;
@@ -293,78 +1670,318 @@ impl S3Client {
         Ok((bytes, content_type))
     }
 
+    /// Uploads a file and returns the ETag S3 assigned to the new object.
     pub async fn upload_file(
         &self,
         bucket: &str,
         key: &str,
         local_path: &str,
-    ) -> Result<()> {
-        let body = ByteStream::from_path(Path::new(local_path))
+    ) -> Result<Option<String>> {
+        self.upload_file_tracked(bucket, key, local_path, &TransferControl::default())
+            .await
+    }
+
+    pub async fn upload_file_tracked(
+        &self,
+        bucket: &str,
+        key: &str,
+        local_path: &str,
+        control: &TransferControl,
+    ) -> Result<Option<String>> {
+        self.upload_file_with(
+            bucket,
+            key,
+            local_path,
+            TransferAttributes::default(),
+            control,
+        )
+        .await
+    }
+
+    async fn upload_file_with(
+        &self,
+        bucket: &str,
+        key: &str,
+        local_path: &str,
+        attributes: TransferAttributes,
+        control: &TransferControl,
+    ) -> Result<Option<String>> {
+        let path = Path::new(local_path);
+        if attributes.links {
+            if let Ok(target) = tokio::fs::read_link(path).await {
+                return self
+                    .upload_symlink(bucket, key, path, &target, control)
+                    .await;
+            }
+        }
+
+        let metadata = tokio::fs::metadata(path)
+            .await
+            .context("Failed to read file metadata")?;
+        let file_size = metadata.len();
+        let user_metadata = upload_metadata(&metadata, attributes.permissions);
+        control.start(0, file_size);
+        control.check_cancelled()?;
+
+        if file_size >= self.multipart.threshold {
+            return self
+                .upload_file_multipart(bucket, key, path, file_size, user_metadata, control)
+                .await;
+        }
+
+        let body = ByteStream::from_path(path)
             .await
             .context("Failed to read file")?;
 
-        self.client
+        let resp = self
+            .client
             .put_object()
             .bucket(bucket)
             .key(key)
+            .set_metadata(Some(user_metadata))
             .body(body)
             .send()
             .await
             .context("Failed to upload object")?;
+        control.advance(file_size);
 
-        Ok(())
+        Ok(resp.e_tag().map(|s| s.to_string()))
     }
 
-    pub async fn create_folder(&self, bucket: &str, key: &str) -> Result<()> {
-        let folder_key = if key.ends_with('/') {
-            key.to_string()
-        } else {
-            format!("{}/", key)
-        };
+    /// Uploads a symlink as a link object holding its target.
+    async fn upload_symlink(
+        &self,
+        bucket: &str,
+        key: &str,
+        path: &Path,
+        target: &Path,
+        control: &TransferControl,
+    ) -> Result<Option<String>> {
+        let metadata = tokio::fs::symlink_metadata(path)
+            .await
+            .context("Failed to read link metadata")?;
+        let mut user_metadata = upload_metadata(&metadata, false);
+        user_metadata.insert(SYMLINK_METADATA.to_string(), "true".to_string());
 
-        self.client
+        let body = link_body(target);
+        let size = body.len() as u64;
+        control.start(0, size);
+
+        let resp = self
+            .client
             .put_object()
             .bucket(bucket)
-            .key(&folder_key)
-            .body(ByteStream::from(vec![]))
+            .key(key)
+            .set_metadata(Some(user_metadata))
+            .body(ByteStream::from(body))
             .send()
             .await
-            .context("Failed to create folder")?;
+            .context("Failed to upload link object")?;
+        control.advance(size);
 
-        Ok(())
+        Ok(resp.e_tag().map(|s| s.to_string()))
     }
 
-    pub async fn upload_folder(
+    async fn upload_file_multipart(
         &self,
         bucket: &str,
-        prefix: &str,
-        local_folder: &str,
-    ) -> Result<()> {
-        let local_path = Path::new(local_folder);
+        key: &str,
+        path: &Path,
+        file_size: u64,
+        mut user_metadata: HashMap<String, String>,
+        control: &TransferControl,
+    ) -> Result<Option<String>> {
+        user_metadata.insert(
+            PART_SIZE_METADATA.to_string(),
+            self.multipart.part_size_for(file_size).to_string(),
+        );
 
-        for entry in WalkDir::new(local_folder).into_iter().filter_map(|e| e.ok()) {
-            if entry.file_type().is_file() {
-                let relative_path = entry
-                    .path()
-                    .strip_prefix(local_path)
-                    .context("Failed to get relative path")?;
+        let resp = self
+            .client
+            .create_multipart_upload()
+            .bucket(bucket)
+            .key(key)
+            .set_metadata(Some(user_metadata))
+            .send()
+            .await
+            .context("Failed to create multipart upload")?;
 
-                let key = if prefix.is_empty() {
-                    relative_path.to_string_lossy().to_string()
-                } else {
-                    format!(
-                        "{}/{}",
-                        prefix.trim_end_matches('/'),
-                        relative_path.to_string_lossy()
-                    )
-                };
+        let upload_id = resp
+            .upload_id()
+            .context("Multipart upload returned no upload ID")?
+            .to_string();
 
-                self.upload_file(bucket, &key, entry.path().to_str().unwrap())
-                    .await?;
-            }
-        }
+        let parts = self
+            .upload_parts(bucket, key, &upload_id, path, file_size, control)
+            .await;
 
-        Ok(())
+        self.finish_multipart_upload(bucket, key, &upload_id, parts)
+            .await
+    }
+
+    /// Uploads every part of `path`, running up to `concurrency` parts at a
+    /// time. Parts that fail in the parallel pass are retried one at a time.
+    async fn upload_parts(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        path: &Path,
+        file_size: u64,
+        control: &TransferControl,
+    ) -> Result<Vec<CompletedPart>> {
+        let part_size = self.multipart.part_size_for(file_size);
+        let part_count = ((file_size + part_size - 1) / part_size).max(1);
+
+        let results: Vec<(i32, u64, Result<CompletedPart>)> = stream::iter(0..part_count)
+            .map(|index| {
+                let offset = index * part_size;
+                let length = part_size.min(file_size - offset);
+                let part_number = (index + 1) as i32;
+                async move {
+                    let result = match control.check_cancelled() {
+                        Ok(()) => {
+                            self.upload_part(bucket, key, upload_id, path, part_number, offset, length)
+                                .await
+                        }
+                        Err(e) => Err(e),
+                    };
+                    if result.is_ok() {
+                        control.advance(length);
+                    }
+                    (part_number, offset, result)
+                }
+            })
+            .buffer_unordered(self.multipart.concurrency.max(1))
+            .collect()
+            .await;
+
+        let mut parts = Vec::with_capacity(results.len());
+        let mut failed = Vec::new();
+        for (part_number, offset, result) in results {
+            match result {
+                Ok(part) => parts.push(part),
+                Err(_) => failed.push((part_number, offset)),
+            }
+        }
+
+        for (part_number, offset) in failed {
+            let length = part_size.min(file_size - offset);
+            let mut attempt = 0;
+            loop {
+                control.check_cancelled()?;
+                attempt += 1;
+                match self
+                    .upload_part(bucket, key, upload_id, path, part_number, offset, length)
+                    .await
+                {
+                    Ok(part) => {
+                        control.advance(length);
+                        parts.push(part);
+                        break;
+                    }
+                    Err(e) if attempt >= self.multipart.max_retries => {
+                        return Err(e.context(format!(
+                            "Part {} failed after {} retries",
+                            part_number, attempt
+                        )));
+                    }
+                    Err(_) => {
+                        tokio::time::sleep(Duration::from_millis(500 * u64::from(attempt))).await;
+                    }
+                }
+            }
+        }
+
+        parts.sort_by_key(|p| p.part_number());
+        Ok(parts)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn upload_part(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        path: &Path,
+        part_number: i32,
+        offset: u64,
+        length: u64,
+    ) -> Result<CompletedPart> {
+        let body = ByteStream::read_from()
+            .path(path)
+            .offset(offset)
+            .length(Length::Exact(length))
+            .build()
+            .await
+            .context("Failed to read file part")?;
+
+        let resp = self
+            .client
+            .upload_part()
+            .bucket(bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .part_number(part_number)
+            .body(body)
+            .send()
+            .await
+            .with_context(|| format!("Failed to upload part {}", part_number))?;
+
+        Ok(CompletedPart::builder()
+            .part_number(part_number)
+            .set_e_tag(resp.e_tag().map(|s| s.to_string()))
+            .build())
+    }
+
+    /// Best-effort cleanup so a failed upload doesn't leave billable parts behind.
+    async fn abort_multipart_upload(&self, bucket: &str, key: &str, upload_id: &str) {
+        let _ = self
+            .client
+            .abort_multipart_upload()
+            .bucket(bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .send()
+            .await;
+    }
+
+    pub async fn create_folder(&self, bucket: &str, key: &str) -> Result<()> {
+        let folder_key = if key.ends_with('/') {
+            key.to_string()
+        } else {
+            format!("{}/", key)
+        };
+
+        self.client
+            .put_object()
+            .bucket(bucket)
+            .key(&folder_key)
+            .body(ByteStream::from(vec![]))
+            .send()
+            .await
+            .context("Failed to create folder")?;
+
+        Ok(())
+    }
+
+    pub async fn upload_folder(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        local_folder: &str,
+        filters: &FilterRules,
+    ) -> Result<()> {
+        let filter = PathFilter::new(Path::new(local_folder), filters)?;
+
+        for (relative_path, path) in filter.files() {
+            let key = join_key(prefix, &relative_path);
+
+            self.upload_file(bucket, &key, path.to_str().unwrap())
+                .await?;
+        }
+
+        Ok(())
     }
 
     pub async fn delete_object(&self, bucket: &str, key: &str) -> Result<()> {
@@ -379,23 +1996,79 @@ impl S3Client {
         Ok(())
     }
 
+    /// Deletes any number of keys, a batch of up to 1000 per DeleteObjects
+    /// request and several requests at a time.
     pub async fn delete_objects(&self, bucket: &str, keys: &[String]) -> Result<DeleteObjectsResult> {
-        use aws_sdk_s3::types::{Delete, ObjectIdentifier};
+        Ok(self
+            .delete_in_batches(bucket, &object_identifiers(keys), |_, _| {})
+            .await)
+    }
+
+    /// Deletes every object under `prefix`, folder marker included, calling
+    /// `progress` with the number of keys handled so far and the total.
+    pub async fn delete_prefix(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        progress: impl Fn(u64, u64) + Send + Sync,
+    ) -> Result<DeleteObjectsResult> {
+        let prefix = folder_prefix(prefix);
+        if prefix.is_empty() {
+            bail!("Refusing to delete everything in the bucket");
+        }
+
+        let keys: Vec<String> = self
+            .list_objects_recursive(bucket, &prefix)
+            .await?
+            .into_iter()
+            .map(|object| object.key)
+            .collect();
+
+        Ok(self
+            .delete_in_batches(bucket, &object_identifiers(&keys), progress)
+            .await)
+    }
+
+    /// How many objects and bytes are under `prefix`, so a deletion can be
+    /// confirmed before it starts.
+    pub async fn summarize_prefix(&self, bucket: &str, prefix: &str) -> Result<PrefixSummary> {
+        let objects = self
+            .list_objects_recursive(bucket, &folder_prefix(prefix))
+            .await?;
+
+        Ok(PrefixSummary {
+            objects: objects.len() as u64,
+            bytes: objects.iter().map(|object| object.size.max(0) as u64).sum(),
+        })
+    }
+
+    /// Splits `objects` into DeleteObjects batches and runs a few at a time.
+    async fn delete_in_batches(
+        &self,
+        bucket: &str,
+        objects: &[ObjectIdentifier],
+        progress: impl Fn(u64, u64) + Send + Sync,
+    ) -> DeleteObjectsResult {
+        delete_each(objects, progress, |batch| self.delete_batch(bucket, batch)).await
+    }
+
+    /// One DeleteObjects request for at most `DELETE_BATCH_SIZE` objects.
+    async fn delete_batch(
+        &self,
+        bucket: &str,
+        objects: &[ObjectIdentifier],
+    ) -> Result<DeleteObjectsResult> {
+        use aws_sdk_s3::types::Delete;
 
-        if keys.is_empty() {
+        if objects.is_empty() {
             return Ok(DeleteObjectsResult {
                 deleted: 0,
                 errors: vec![],
             });
         }
 
-        let objects: Vec<ObjectIdentifier> = keys
-            .iter()
-            .filter_map(|key| ObjectIdentifier::builder().key(key).build().ok())
-            .collect();
-
         let delete = Delete::builder()
-            .set_objects(Some(objects))
+            .set_objects(Some(objects.to_vec()))
             .build()
             .context("Failed to build delete request")?;
 
@@ -415,200 +2088,1710 @@ impl S3Client {
             .map(|e| DeleteError {
                 key: e.key().unwrap_or_default().to_string(),
                 message: e.message().unwrap_or_default().to_string(),
+                code: e.code().map(|c| c.to_string()),
             })
             .collect();
 
         Ok(DeleteObjectsResult { deleted, errors })
     }
 
-    pub async fn presign_get_url(
+    /// Copies an object inside the storage service without downloading it.
+    /// Metadata, tags, content type and storage class come along unless
+    /// `overrides` replaces them. Returns the new object's ETag.
+    pub async fn copy_object(
         &self,
+        source_bucket: &str,
+        source_key: &str,
         bucket: &str,
         key: &str,
-        expires_in_secs: u64,
-    ) -> Result<String> {
-        let presigning_config = PresigningConfig::expires_in(Duration::from_secs(expires_in_secs))
-            .context("Invalid expiration duration")?;
+        overrides: &CopyOverrides,
+    ) -> Result<Option<String>> {
+        self.copy_object_version(source_bucket, source_key, None, bucket, key, overrides)
+            .await
+    }
 
-        let presigned = self
+    /// `copy_object` for a given version of the source, or its current
+    /// version when `version_id` is `None`.
+    async fn copy_object_version(
+        &self,
+        source_bucket: &str,
+        source_key: &str,
+        version_id: Option<&str>,
+        bucket: &str,
+        key: &str,
+        overrides: &CopyOverrides,
+    ) -> Result<Option<String>> {
+        let head = self
             .client
-            .get_object()
+            .head_object()
+            .bucket(source_bucket)
+            .key(source_key)
+            .set_version_id(version_id.map(str::to_string))
+            .send()
+            .await
+            .context("Failed to get object metadata")?;
+        let source = copy_source(source_bucket, source_key, version_id);
+
+        if head.content_length().unwrap_or(0).max(0) as u64 > MAX_COPY_OBJECT_SIZE {
+            // Multipart uploads don't copy tags by themselves.
+            let tags = match &overrides.tags {
+                Some(tags) => Some(tags.clone()),
+                None => {
+                    self.object_tags(source_bucket, source_key, version_id)
+                        .await
+                }
+            };
+            return self
+                .copy_object_multipart(&source, bucket, key, &head, overrides, tags)
+                .await;
+        }
+
+        let mut request = self
+            .client
+            .copy_object()
+            .copy_source(source)
             .bucket(bucket)
             .key(key)
-            .presigned(presigning_config)
+            // Without this the copy lands in STANDARD whatever the source used.
+            .set_storage_class(head.storage_class().cloned());
+
+        if overrides.metadata.is_some() || overrides.content_type.is_some() {
+            // REPLACE drops every header that isn't sent again, so the ones
+            // not being overridden are carried over by hand.
+            request = request
+                .metadata_directive(MetadataDirective::Replace)
+                .set_metadata(
+                    overrides
+                        .metadata
+                        .clone()
+                        .or_else(|| head.metadata().cloned()),
+                )
+                .set_content_type(
+                    overrides
+                        .content_type
+                        .clone()
+                        .or_else(|| head.content_type().map(str::to_string)),
+                )
+                .set_cache_control(head.cache_control().map(str::to_string))
+                .set_content_disposition(head.content_disposition().map(str::to_string))
+                .set_content_encoding(head.content_encoding().map(str::to_string))
+                .set_content_language(head.content_language().map(str::to_string));
+        }
+
+        if let Some(tags) = &overrides.tags {
+            request = request
+                .tagging_directive(TaggingDirective::Replace)
+                .tagging(tagging_query(tags));
+        }
+
+        let resp = request.send().await.context("Failed to copy object")?;
+
+        Ok(resp
+            .copy_object_result()
+            .and_then(|result| result.e_tag())
+            .map(|s| s.to_string()))
+    }
+
+    /// Copies an object too big for CopyObject with a multipart upload
+    /// whose parts are copied from ranges of the source.
+    async fn copy_object_multipart(
+        &self,
+        copy_source: &str,
+        bucket: &str,
+        key: &str,
+        head: &HeadObjectOutput,
+        overrides: &CopyOverrides,
+        tags: Option<HashMap<String, String>>,
+    ) -> Result<Option<String>> {
+        let size = head.content_length().unwrap_or(0).max(0) as u64;
+        let part_size = self.multipart.part_size_for(size);
+
+        let mut user_metadata = overrides
+            .metadata
+            .clone()
+            .or_else(|| head.metadata().cloned())
+            .unwrap_or_default();
+        user_metadata.insert(PART_SIZE_METADATA.to_string(), part_size.to_string());
+
+        let resp = self
+            .client
+            .create_multipart_upload()
+            .bucket(bucket)
+            .key(key)
+            .set_metadata(Some(user_metadata))
+            .set_content_type(
+                overrides
+                    .content_type
+                    .clone()
+                    .or_else(|| head.content_type().map(str::to_string)),
+            )
+            .set_cache_control(head.cache_control().map(str::to_string))
+            .set_content_disposition(head.content_disposition().map(str::to_string))
+            .set_content_encoding(head.content_encoding().map(str::to_string))
+            .set_content_language(head.content_language().map(str::to_string))
+            .set_storage_class(head.storage_class().cloned())
+            .set_tagging(
+                tags.filter(|tags| !tags.is_empty())
+                    .map(|tags| tagging_query(&tags)),
+            )
+            .send()
             .await
-            .context("Failed to generate presigned URL")?;
+            .context("Failed to create multipart upload")?;
 
-        Ok(presigned.uri().to_string())
+        let upload_id = resp
+            .upload_id()
+            .context("Multipart upload returned no upload ID")?
+            .to_string();
+
+        let part_count = (size + part_size - 1) / part_size;
+        let copies: Vec<_> = (0..part_count)
+            .map(|index| {
+                let offset = index * part_size;
+                let last = (offset + part_size).min(size) - 1;
+                let part_number = (index + 1) as i32;
+                let upload_id = &upload_id;
+                async move {
+                    let resp = self
+                        .client
+                        .upload_part_copy()
+                        .copy_source(copy_source)
+                        .copy_source_range(format!("bytes={}-{}", offset, last))
+                        .bucket(bucket)
+                        .key(key)
+                        .upload_id(upload_id)
+                        .part_number(part_number)
+                        .send()
+                        .await
+                        .with_context(|| format!("Failed to copy part {}", part_number))?;
+
+                    Ok(CompletedPart::builder()
+                        .part_number(part_number)
+                        .set_e_tag(
+                            resp.copy_part_result()
+                                .and_then(|result| result.e_tag())
+                                .map(|s| s.to_string()),
+                        )
+                        .build())
+                }
+                .boxed()
+            })
+            .collect();
+
+        let results: Vec<Result<CompletedPart>> = stream::iter(copies)
+            .buffer_unordered(self.multipart.concurrency.max(1))
+            .collect()
+            .await;
+        let parts = results
+            .into_iter()
+            .collect::<Result<Vec<_>>>()
+            .map(|mut parts| {
+                parts.sort_by_key(|p| p.part_number());
+                parts
+            });
+
+        self.finish_multipart_upload(bucket, key, &upload_id, parts)
+            .await
     }
 
-    pub async fn sync_folder(
+    /// Completes a multipart upload with its parts, or aborts it if they
+    /// couldn't all be uploaded or completing fails.
+    async fn finish_multipart_upload(
         &self,
         bucket: &str,
-        prefix: &str,
-        local_folder: &str,
-        direction: SyncDirection,
-    ) -> Result<SyncResult> {
-        let mut result = SyncResult::default();
+        key: &str,
+        upload_id: &str,
+        parts: Result<Vec<CompletedPart>>,
+    ) -> Result<Option<String>> {
+        let completed = match parts {
+            Ok(parts) => self
+                .client
+                .complete_multipart_upload()
+                .bucket(bucket)
+                .key(key)
+                .upload_id(upload_id)
+                .multipart_upload(
+                    CompletedMultipartUpload::builder()
+                        .set_parts(Some(parts))
+                        .build(),
+                )
+                .send()
+                .await
+                .context("Failed to complete multipart upload"),
+            Err(e) => Err(e),
+        };
 
-        match direction {
-            SyncDirection::LocalToRemote => {
-                self.sync_local_to_remote(bucket, prefix, local_folder, &mut result)
-                    .await?;
-            }
-            SyncDirection::RemoteToLocal => {
-                self.sync_remote_to_local(bucket, prefix, local_folder, &mut result)
-                    .await?;
+        match completed {
+            Ok(resp) => Ok(resp.e_tag().map(|s| s.to_string())),
+            Err(e) => {
+                self.abort_multipart_upload(bucket, key, upload_id).await;
+                Err(e)
             }
         }
-
-        Ok(result)
     }
 
-    async fn sync_local_to_remote(
+    /// An object's tags, or `None` if they can't be read. Not every
+    /// provider supports tagging, so a missing tag set isn't an error.
+    async fn object_tags(
         &self,
         bucket: &str,
-        prefix: &str,
-        local_folder: &str,
-        result: &mut SyncResult,
-    ) -> Result<()> {
-        let local_path = Path::new(local_folder);
-        let remote_objects = self.list_all_objects(bucket, Some(prefix)).await?;
+        key: &str,
+        version_id: Option<&str>,
+    ) -> Option<HashMap<String, String>> {
+        let resp = self
+            .client
+            .get_object_tagging()
+            .bucket(bucket)
+            .key(key)
+            .set_version_id(version_id.map(str::to_string))
+            .send()
+            .await
+            .ok()?;
 
-        let remote_map: std::collections::HashMap<String, ObjectInfo> = remote_objects
-            .into_iter()
-            .map(|o| (o.key.clone(), o))
-            .collect();
+        Some(
+            resp.tag_set()
+                .iter()
+                .map(|tag| (tag.key().to_string(), tag.value().to_string()))
+                .collect(),
+        )
+    }
 
-        for entry in WalkDir::new(local_folder).into_iter().filter_map(|e| e.ok()) {
-            if entry.file_type().is_file() {
-                let relative_path = entry
-                    .path()
-                    .strip_prefix(local_path)
-                    .context("Failed to get relative path")?;
+    /// Copies an object and then deletes the source.
+    pub async fn move_object(
+        &self,
+        source_bucket: &str,
+        source_key: &str,
+        bucket: &str,
+        key: &str,
+        overrides: &CopyOverrides,
+    ) -> Result<Option<String>> {
+        if source_bucket == bucket && source_key == key {
+            bail!("Can't move {} onto itself", key);
+        }
 
-                let key = if prefix.is_empty() {
-                    relative_path.to_string_lossy().to_string()
-                } else {
-                    format!(
-                        "{}/{}",
-                        prefix.trim_end_matches('/'),
-                        relative_path.to_string_lossy()
-                    )
-                };
+        let etag = self
+            .copy_object(source_bucket, source_key, bucket, key, overrides)
+            .await?;
+        self.delete_object(source_bucket, source_key).await?;
+        Ok(etag)
+    }
 
-                let local_etag = self.compute_local_etag(entry.path()).await?;
+    /// Moves every object under `from` to the same place under `to`,
+    /// calling `progress` with the number of objects copied so far, the
+    /// total and the key just handled. A source object is only deleted
+    /// once its copy exists; objects that fail stay where they were.
+    pub async fn rename_prefix(
+        &self,
+        bucket: &str,
+        from: &str,
+        to: &str,
+        progress: impl Fn(u64, u64, &str) + Send + Sync,
+    ) -> Result<RenamePrefixResult> {
+        let from = folder_prefix(from);
+        let to = folder_prefix(to);
+        if from.is_empty() || to.is_empty() {
+            bail!("Can't rename the root of a bucket");
+        }
+        if from.starts_with(&to) || to.starts_with(&from) {
+            bail!("Can't move {} to {}, one is inside the other", from, to);
+        }
 
-                if let Some(remote_obj) = remote_map.get(&key) {
-                    let remote_etag = remote_obj
-                        .etag
-                        .as_ref()
-                        .map(|e| e.trim_matches('"').to_string());
+        let objects = self.list_objects_recursive(bucket, &from).await?;
+        let total = objects.len() as u64;
+        let done = AtomicU64::new(0);
+        let overrides = CopyOverrides::default();
 
-                    if Some(local_etag.clone()) == remote_etag {
-                        result.skipped += 1;
-                        continue;
-                    }
+        let copies: Vec<_> = objects
+            .iter()
+            .map(|object| {
+                let target = format!("{}{}", to, &object.key[from.len()..]);
+                let (done, progress, overrides) = (&done, &progress, &overrides);
+                async move {
+                    let result = self
+                        .copy_object(bucket, &object.key, bucket, &target, overrides)
+                        .await;
+                    progress(done.fetch_add(1, Ordering::Relaxed) + 1, total, &object.key);
+                    (object.key.clone(), result)
                 }
+                .boxed()
+            })
+            .collect();
 
-                self.upload_file(bucket, &key, entry.path().to_str().unwrap())
-                    .await?;
-                result.uploaded += 1;
+        let copied: Vec<(String, Result<Option<String>>)> = stream::iter(copies)
+            .buffer_unordered(self.sync_concurrency.transfers.max(1))
+            .collect()
+            .await;
+
+        let mut result = RenamePrefixResult::default();
+        let mut sources = Vec::new();
+        for (key, outcome) in copied {
+            match outcome {
+                Ok(_) => sources.push(key),
+                Err(e) => result.errors.push(ObjectError::new(&key, &e)),
             }
         }
 
-        Ok(())
+        let deleted = self
+            .delete_in_batches(bucket, &object_identifiers(&sources), |_, _| {})
+            .await;
+        result.moved = deleted.deleted;
+        result
+            .errors
+            .extend(deleted.errors.into_iter().map(|e| ObjectError {
+                key: e.key,
+                message: e.message,
+                code: e.code,
+            }));
+
+        Ok(result)
     }
 
-    async fn sync_remote_to_local(
+    /// Copies an object that `source` can read into this client's bucket.
+    /// Clients sharing an account copy server-side; otherwise the object
+    /// streams through memory, uploaded in parts as it arrives when it is
+    /// over the multipart threshold. Metadata, content type and tags come
+    /// along either way.
+    pub async fn copy_object_from(
         &self,
+        source: &S3Client,
+        source_bucket: &str,
+        source_key: &str,
         bucket: &str,
-        prefix: &str,
-        local_folder: &str,
-        result: &mut SyncResult,
-    ) -> Result<()> {
-        let local_path = Path::new(local_folder);
-        tokio::fs::create_dir_all(local_path)
+        key: &str,
+    ) -> Result<Option<String>> {
+        if self.shares_account_with(source) {
+            return self
+                .copy_object(
+                    source_bucket,
+                    source_key,
+                    bucket,
+                    key,
+                    &CopyOverrides::default(),
+                )
+                .await;
+        }
+
+        let resp = source
+            .client
+            .get_object()
+            .bucket(source_bucket)
+            .key(source_key)
+            .send()
             .await
-            .context("Failed to create local folder")?;
+            .context("Failed to download object")?;
+        let size = resp.content_length().unwrap_or(0).max(0) as u64;
+        let tagging = source
+            .object_tags(source_bucket, source_key, None)
+            .await
+            .filter(|tags| !tags.is_empty())
+            .map(|tags| tagging_query(&tags));
 
-        let remote_objects = self.list_all_objects(bucket, Some(prefix)).await?;
+        // The source's part size says nothing about how the copy is uploaded.
+        let mut user_metadata = resp.metadata().cloned().unwrap_or_default();
+        user_metadata.remove(PART_SIZE_METADATA);
 
-        for obj in remote_objects {
-            if obj.is_folder {
-                continue;
-            }
+        if size < self.multipart.threshold {
+            let request = self
+                .client
+                .put_object()
+                .bucket(bucket)
+                .key(key)
+                .set_metadata(Some(user_metadata))
+                .set_content_type(resp.content_type().map(str::to_string))
+                .set_cache_control(resp.cache_control().map(str::to_string))
+                .set_content_disposition(resp.content_disposition().map(str::to_string))
+                .set_content_encoding(resp.content_encoding().map(str::to_string))
+                .set_content_language(resp.content_language().map(str::to_string))
+                .set_tagging(tagging);
+            let body = resp
+                .body
+                .collect()
+                .await
+                .context("Failed to download object")?
+                .into_bytes();
 
-            let relative_key = if prefix.is_empty() {
-                obj.key.clone()
-            } else {
-                obj.key
-                    .strip_prefix(prefix.trim_end_matches('/'))
-                    .unwrap_or(&obj.key)
-                    .trim_start_matches('/')
-                    .to_string()
-            };
+            let put = request
+                .body(ByteStream::from(body))
+                .send()
+                .await
+                .context("Failed to upload object")?;
+            return Ok(put.e_tag().map(|s| s.to_string()));
+        }
+
+        let part_size = self.multipart.part_size_for(size);
+        user_metadata.insert(PART_SIZE_METADATA.to_string(), part_size.to_string());
+
+        let created = self
+            .client
+            .create_multipart_upload()
+            .bucket(bucket)
+            .key(key)
+            .set_metadata(Some(user_metadata))
+            .set_content_type(resp.content_type().map(str::to_string))
+            .set_cache_control(resp.cache_control().map(str::to_string))
+            .set_content_disposition(resp.content_disposition().map(str::to_string))
+            .set_content_encoding(resp.content_encoding().map(str::to_string))
+            .set_content_language(resp.content_language().map(str::to_string))
+            .set_tagging(tagging)
+            .send()
+            .await
+            .context("Failed to create multipart upload")?;
 
-            let local_file_path = local_path.join(&relative_key);
+        let upload_id = created
+            .upload_id()
+            .context("Multipart upload returned no upload ID")?
+            .to_string();
 
-            if local_file_path.exists() {
-                let local_etag = self.compute_local_etag(&local_file_path).await?;
-                let remote_etag = obj.etag.as_ref().map(|e| e.trim_matches('"').to_string());
+        let mut reader = resp.body.into_async_read();
+        let parts = async {
+            // Reading stays ahead of the uploads by at most `concurrency`
+            // parts, which bounds how much of the object sits in memory.
+            let concurrency = self.multipart.concurrency.max(1);
+            let mut in_flight = FuturesUnordered::new();
+            let mut parts = Vec::new();
+            let mut offset = 0;
+            let mut part_number = 1;
 
-                if Some(local_etag) == remote_etag {
-                    result.skipped += 1;
+            while offset < size || !in_flight.is_empty() {
+                if offset >= size || in_flight.len() >= concurrency {
+                    if let Some(part) = in_flight.next().await {
+                        parts.push(part?);
+                    }
                     continue;
                 }
+
+                let length = part_size.min(size - offset);
+                let mut buffer = vec![0u8; length as usize];
+                {
+                    // Parts already read keep uploading while this one is.
+                    let read = reader.read_exact(&mut buffer);
+                    tokio::pin!(read);
+                    loop {
+                        tokio::select! {
+                            read = &mut read => {
+                                read.context("Failed to download object")?;
+                                break;
+                            }
+                            Some(part) = in_flight.next() => parts.push(part?),
+                        }
+                    }
+                }
+                in_flight.push(self.upload_part_bytes(
+                    bucket,
+                    key,
+                    &upload_id,
+                    part_number,
+                    Bytes::from(buffer),
+                ));
+                offset += length;
+                part_number += 1;
             }
 
-            self.download_object(bucket, &obj.key, local_file_path.to_str().unwrap())
-                .await?;
-            result.downloaded += 1;
+            parts.sort_by_key(|p: &CompletedPart| p.part_number());
+            Ok(parts)
         }
+        .await;
 
-        Ok(())
+        self.finish_multipart_upload(bucket, key, &upload_id, parts)
+            .await
     }
 
-    async fn list_all_objects(
+    /// Uploads one part held in memory, retrying it like file parts are.
+    async fn upload_part_bytes(
         &self,
         bucket: &str,
-        prefix: Option<&str>,
-    ) -> Result<Vec<ObjectInfo>> {
-        let mut all_objects = Vec::new();
-        let mut continuation_token: Option<String> = None;
-
+        key: &str,
+        upload_id: &str,
+        part_number: i32,
+        body: Bytes,
+    ) -> Result<CompletedPart> {
+        let mut attempt = 0;
         loop {
-            let result = self
-                .list_objects(bucket, prefix, continuation_token.as_deref(), 1000)
-                .await?;
-
-            all_objects.extend(result.objects);
+            attempt += 1;
+            let sent = self
+                .client
+                .upload_part()
+                .bucket(bucket)
+                .key(key)
+                .upload_id(upload_id)
+                .part_number(part_number)
+                .body(ByteStream::from(body.clone()))
+                .send()
+                .await;
 
-            if !result.is_truncated {
-                break;
+            match sent {
+                Ok(resp) => {
+                    return Ok(CompletedPart::builder()
+                        .part_number(part_number)
+                        .set_e_tag(resp.e_tag().map(|s| s.to_string()))
+                        .build());
+                }
+                Err(e) if attempt > self.multipart.max_retries => {
+                    return Err(anyhow::Error::new(e).context(format!(
+                        "Part {} failed after {} retries",
+                        part_number,
+                        attempt - 1
+                    )));
+                }
+                Err(_) => {
+                    tokio::time::sleep(Duration::from_millis(500 * u64::from(attempt))).await;
+                }
             }
-
-            continuation_token = result.next_continuation_token;
         }
-
-        Ok(all_objects)
     }
 
-    async fn compute_local_etag(&self, path: &Path) -> Result<String> {
-        let mut file = File::open(path).await.context("Failed to open file")?;
-        let mut hasher = Md5::new();
-        let mut buffer = vec![0u8; 8192];
+    /// Copies every object under `source_prefix` that `source` can read to
+    /// the same place under `prefix` in this client's bucket, calling
+    /// `progress` with the number of objects handled so far, the total and
+    /// the key just handled.
+    pub async fn copy_prefix_from(
+        &self,
+        source: &S3Client,
+        source_bucket: &str,
+        source_prefix: &str,
+        bucket: &str,
+        prefix: &str,
+        progress: impl Fn(u64, u64, &str) + Send + Sync,
+    ) -> Result<CopyPrefixResult> {
+        let source_prefix = folder_prefix(source_prefix);
+        let prefix = folder_prefix(prefix);
+
+        let objects = source
+            .list_objects_recursive(source_bucket, &source_prefix)
+            .await?;
+        let total = objects.len() as u64;
+        let done = AtomicU64::new(0);
+
+        let copies: Vec<_> = objects
+            .iter()
+            .map(|object| {
+                let target = format!("{}{}", prefix, &object.key[source_prefix.len()..]);
+                let (done, progress) = (&done, &progress);
+                async move {
+                    let result = self
+                        .copy_object_from(source, source_bucket, &object.key, bucket, &target)
+                        .await;
+                    progress(done.fetch_add(1, Ordering::Relaxed) + 1, total, &object.key);
+                    (object.key.clone(), result)
+                }
+                .boxed()
+            })
+            .collect();
+
+        let copied: Vec<(String, Result<Option<String>>)> = stream::iter(copies)
+            .buffer_unordered(self.sync_concurrency.transfers.max(1))
+            .collect()
+            .await;
+
+        let mut result = CopyPrefixResult::default();
+        for (key, outcome) in copied {
+            match outcome {
+                Ok(_) => result.copied += 1,
+                Err(e) => result.errors.push(ObjectError::new(&key, &e)),
+            }
+        }
+
+        Ok(result)
+    }
+
+    pub async fn presign_get_url(
+        &self,
+        bucket: &str,
+        key: &str,
+        expires_in_secs: u64,
+    ) -> Result<String> {
+        let presigning_config = PresigningConfig::expires_in(Duration::from_secs(expires_in_secs))
+            .context("Invalid expiration duration")?;
+
+        let presigned = self
+            .client
+            .get_object()
+            .bucket(bucket)
+            .key(key)
+            .presigned(presigning_config)
+            .await
+            .context("Failed to generate presigned URL")?;
+
+        Ok(presigned.uri().to_string())
+    }
+
+    pub async fn sync_folder(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        local_folder: &str,
+        direction: SyncDirection,
+        options: &SyncOptions,
+    ) -> Result<SyncResult> {
+        let plan = self
+            .plan_sync(bucket, prefix, local_folder, direction, options)
+            .await?;
+        self.execute_sync_plan(&plan).await
+    }
+
+    /// Runs the comparison behind `sync_folder` without changing either
+    /// side, so the plan can be reviewed and then handed to
+    /// `execute_sync_plan`.
+    pub async fn plan_sync(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        local_folder: &str,
+        direction: SyncDirection,
+        options: &SyncOptions,
+    ) -> Result<SyncPlan> {
+        if direction == SyncDirection::Bidirectional && options.symlinks == SymlinkPolicy::Store {
+            bail!("Storing symlinks as link objects isn't supported for bidirectional sync");
+        }
+        let filter =
+            PathFilter::new(Path::new(local_folder), &options.filters)?.symlinks(options.symlinks);
+
+        let mut errors = Vec::new();
+        let actions = match direction {
+            SyncDirection::LocalToRemote => {
+                self.plan_local_to_remote(bucket, prefix, &filter, options, &mut errors)
+                    .await
+            }
+            SyncDirection::RemoteToLocal => {
+                self.plan_remote_to_local(bucket, prefix, &filter, options, &mut errors)
+                    .await
+            }
+            SyncDirection::Bidirectional => {
+                self.plan_bidirectional(bucket, prefix, &filter, options, &mut errors)
+                    .await
+            }
+        };
+        // Keep whatever was hashed, even if planning failed part way.
+        let _ = hash_cache::save_shared().await;
+
+        Ok(SyncPlan {
+            bucket: bucket.to_string(),
+            prefix: prefix.to_string(),
+            local_path: local_folder.to_string(),
+            direction,
+            actions: actions?,
+            errors,
+            symlinks: options.symlinks,
+            preserve_permissions: options.preserve_permissions,
+        })
+    }
+
+    /// Objects under the sync prefix keyed by their path relative to it,
+    /// leaving out folder markers and keys the filter rejects.
+    pub async fn remote_files(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        filter: &PathFilter,
+    ) -> Result<HashMap<String, ObjectInfo>> {
+        let remote_prefix = folder_prefix(prefix);
+        Ok(self
+            .list_objects_recursive(bucket, &remote_prefix)
+            .await?
+            .into_iter()
+            .filter(|o| !o.is_folder)
+            .map(|o| (o.key[remote_prefix.len()..].to_string(), o))
+            .filter(|(path, _)| filter.is_included(path))
+            .collect())
+    }
+
+    async fn plan_local_to_remote(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        filter: &PathFilter,
+        options: &SyncOptions,
+        errors: &mut Vec<SyncFileError>,
+    ) -> Result<Vec<PlannedAction>> {
+        let remote = self.remote_files(bucket, prefix, filter).await?;
+        let local: BTreeMap<String, PathBuf> = filter.files().into_iter().collect();
+
+        let mut actions = plan_each(
+            local.keys(),
+            self.sync_concurrency.hashing,
+            errors,
+            |path| {
+                self.plan_upload(
+                    bucket,
+                    prefix,
+                    path,
+                    &local[path],
+                    remote.get(path),
+                    options,
+                )
+            },
+        )
+        .await;
+
+        if options.delete {
+            let mut extraneous: Vec<&String> = remote
+                .keys()
+                .filter(|path| !local.contains_key(*path))
+                .collect();
+            extraneous.sort();
+            check_delete_cap(extraneous.len(), remote.len(), options.max_delete_percent)?;
+
+            actions.extend(extraneous.into_iter().map(|path| {
+                let obj = &remote[path];
+                let mut planned = PlannedAction::new(
+                    path,
+                    SyncAction::DeleteRemote,
+                    obj.size.max(0) as u64,
+                    "Not in the local folder",
+                );
+                planned.remote_etag = obj.etag.clone();
+                planned
+            }));
+        }
+
+        Ok(actions)
+    }
+
+    async fn plan_upload(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        path: &str,
+        file: &Path,
+        remote: Option<&ObjectInfo>,
+        options: &SyncOptions,
+    ) -> Result<PlannedAction> {
+        if let Some(target) = stored_link(file, options.symlinks) {
+            return Ok(plan_link(path, &target, remote, SyncAction::Upload));
+        }
+
+        let mode = options.compare_mode;
+        let size = file_size(file).await?;
+
+        if mode != CompareMode::Checksum {
+            let matched = match remote {
+                Some(obj) => Some(
+                    self.metadata_matches_remote(bucket, file, obj, mode)
+                        .await?,
+                ),
+                None => None,
+            };
+            let (action, reason) = match matched {
+                Some(true) => (SyncAction::Skip, "Unchanged"),
+                Some(false) => (SyncAction::Upload, mode.mismatch_reason()),
+                None => (SyncAction::Upload, "Missing on the remote"),
+            };
+            let mut planned = PlannedAction::new(path, action, size, reason);
+            planned.remote_etag = remote.and_then(|o| o.etag.clone());
+            return Ok(planned);
+        }
+
+        let local_etag = self.compute_local_etag(file).await?;
+
+        let mut sync_entry = SyncEntry::new(path.to_string()).with_local_md5(local_etag.clone());
+        if let Some(obj) = remote {
+            if let Some(remote_etag) = obj.etag.clone() {
+                sync_entry = sync_entry.with_remote_etag(remote_etag);
+            }
+            let same = self
+                .matches_remote(bucket, &join_key(prefix, path), file, &local_etag, obj)
+                .await?;
+            sync_entry = sync_entry.with_same_content(same);
+        }
+        sync_entry.determine_sync_action();
+        Ok(PlannedAction::from_entry(&sync_entry, size))
+    }
+
+    async fn plan_remote_to_local(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        filter: &PathFilter,
+        options: &SyncOptions,
+        errors: &mut Vec<SyncFileError>,
+    ) -> Result<Vec<PlannedAction>> {
+        let local_root = filter.root();
+        let remote = self.remote_files(bucket, prefix, filter).await?;
+
+        let mut actions = plan_each(
+            remote.keys(),
+            self.sync_concurrency.hashing,
+            errors,
+            |path| self.plan_download(bucket, path, local_root.join(path), &remote[path], options),
+        )
+        .await;
+
+        if options.delete {
+            let local: BTreeMap<String, PathBuf> = filter.files().into_iter().collect();
+            let extraneous: Vec<(&String, &PathBuf)> = local
+                .iter()
+                .filter(|(path, _)| !remote.contains_key(*path))
+                .collect();
+            check_delete_cap(extraneous.len(), local.len(), options.max_delete_percent)?;
+
+            for (path, file) in extraneous {
+                // The hash lets the delete be skipped if the file changes
+                // before the plan runs.
+                let (size, local_md5) = match stored_link(file, options.symlinks) {
+                    Some(target) => (link_body(&target).len() as u64, None),
+                    None => (
+                        file_size(file).await?,
+                        Some(self.compute_local_etag(file).await?),
+                    ),
+                };
+                let mut planned =
+                    PlannedAction::new(path, SyncAction::DeleteLocal, size, "Not on the remote");
+                planned.local_md5 = local_md5;
+                actions.push(planned);
+            }
+        }
+
+        Ok(actions)
+    }
+
+    async fn plan_download(
+        &self,
+        bucket: &str,
+        path: &str,
+        local_file_path: PathBuf,
+        obj: &ObjectInfo,
+        options: &SyncOptions,
+    ) -> Result<PlannedAction> {
+        if let Some(target) = stored_link(&local_file_path, options.symlinks) {
+            return Ok(plan_link(path, &target, Some(obj), SyncAction::Download));
+        }
+
+        let mode = options.compare_mode;
+        let mut local_md5 = None;
+        let (action, reason) = if !local_file_path.exists() {
+            (SyncAction::Download, "Missing locally")
+        } else if mode != CompareMode::Checksum {
+            if self
+                .metadata_matches_remote(bucket, &local_file_path, obj, mode)
+                .await?
+            {
+                (SyncAction::Skip, "Unchanged")
+            } else {
+                (SyncAction::Download, mode.mismatch_reason())
+            }
+        } else {
+            let local_etag = self.compute_local_etag(&local_file_path).await?;
+            let unchanged = self
+                .matches_remote(bucket, &obj.key, &local_file_path, &local_etag, obj)
+                .await?;
+            local_md5 = Some(local_etag);
+
+            if unchanged {
+                (SyncAction::Skip, "Unchanged")
+            } else {
+                (SyncAction::Download, "Content differs")
+            }
+        };
+
+        let mut planned = PlannedAction::new(path, action, obj.size.max(0) as u64, reason);
+        planned.local_md5 = local_md5;
+        planned.remote_etag = obj.etag.clone();
+        Ok(planned)
+    }
+
+    async fn plan_bidirectional(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        filter: &PathFilter,
+        options: &SyncOptions,
+        errors: &mut Vec<SyncFileError>,
+    ) -> Result<Vec<PlannedAction>> {
+        let db = SyncDatabase::load(
+            &self.profile_id,
+            bucket,
+            prefix,
+            &filter.root().to_string_lossy(),
+        )?;
+        let remote = self.remote_files(bucket, prefix, filter).await?;
+        let local: BTreeMap<String, PathBuf> = filter.files().into_iter().collect();
+
+        let mut paths: Vec<String> = local
+            .keys()
+            .chain(remote.keys())
+            .chain(db.records.keys())
+            .cloned()
+            .collect();
+        paths.sort();
+        paths.dedup();
+
+        let actions = plan_each(&paths, self.sync_concurrency.hashing, errors, |path| {
+            self.plan_two_way(
+                bucket,
+                path,
+                db.records.get(path),
+                local.get(path),
+                remote.get(path),
+                options,
+            )
+        })
+        .await;
+
+        Ok(actions)
+    }
+
+    async fn plan_two_way(
+        &self,
+        bucket: &str,
+        path: &str,
+        base: Option<&SyncRecord>,
+        local_path: Option<&PathBuf>,
+        remote_obj: Option<&ObjectInfo>,
+        options: &SyncOptions,
+    ) -> Result<PlannedAction> {
+        let mut entry = SyncEntry::new(path.to_string());
+        if let Some(local_path) = local_path {
+            let md5 = self.local_md5_since(local_path, base).await?;
+            entry = entry.with_local_md5(md5);
+        }
+        if let Some(etag) = remote_obj.and_then(|o| o.etag.clone()) {
+            entry = entry.with_remote_etag(etag);
+        }
+        if let Some(base) = base {
+            entry = entry.with_base(base.clone());
+        }
+        entry.determine_bidirectional_action();
+
+        // Tell real conflicts apart from ETags that just aren't an MD5.
+        if let (SyncAction::Conflict, Some(local_path), Some(obj), Some(md5)) = (
+            entry.action,
+            local_path,
+            remote_obj,
+            entry.local_md5.clone(),
+        ) {
+            let same = match options.compare_mode {
+                CompareMode::Checksum => {
+                    self.matches_remote(bucket, &obj.key, local_path, &md5, obj)
+                        .await?
+                }
+                mode => {
+                    self.metadata_matches_remote(bucket, local_path, obj, mode)
+                        .await?
+                }
+            };
+            if same {
+                entry = entry.with_same_content(true);
+                entry.determine_bidirectional_action();
+            }
+        }
+
+        let local_size = match local_path {
+            Some(local_path) => Some(file_size(local_path).await?),
+            None => None,
+        };
+        let remote_size = remote_obj.map(|o| o.size.max(0) as u64);
+        let size = match entry.action {
+            SyncAction::Download | SyncAction::DeleteRemote => remote_size,
+            _ => local_size.or(remote_size),
+        };
+
+        let mut planned = PlannedAction::from_entry(&entry, size.unwrap_or(0));
+        if entry.action == SyncAction::Conflict {
+            let mut conflict =
+                local_conflict(path.to_string(), local_path.map(|p| p.as_path())).await?;
+            if let Some(obj) = remote_obj {
+                conflict.remote_size = Some(obj.size);
+                conflict.remote_modified = obj.last_modified;
+            }
+            conflict.resolution = options.conflict_policy.resolve(&conflict);
+            planned.conflict = Some(conflict);
+        }
+        Ok(planned)
+    }
+
+    /// Carries out a plan from `plan_sync` as it stands; nothing is compared
+    /// again, so a plan should be executed soon after it was made. A file
+    /// that fails is recorded in the result and the rest still runs.
+    pub async fn execute_sync_plan(&self, plan: &SyncPlan) -> Result<SyncResult> {
+        let local_root = Path::new(&plan.local_path);
+        tokio::fs::create_dir_all(local_root)
+            .await
+            .context("Failed to create local folder")?;
+
+        // Only bidirectional syncs remember what both sides agreed on.
+        let mut db = match plan.direction {
+            SyncDirection::Bidirectional => Some(SyncDatabase::load(
+                &self.profile_id,
+                &plan.bucket,
+                &plan.prefix,
+                &plan.local_path,
+            )?),
+            _ => None,
+        };
+
+        let mut result = SyncResult {
+            errors: plan.errors.clone(),
+            ..SyncResult::default()
+        };
+        self.execute_actions(plan, db.as_mut(), &mut result).await;
+
+        let _ = hash_cache::save_shared().await;
+        if let Some(db) = &db {
+            db.save()?;
+        }
+        Ok(result)
+    }
+
+    async fn execute_actions(
+        &self,
+        plan: &SyncPlan,
+        mut db: Option<&mut SyncDatabase>,
+        result: &mut SyncResult,
+    ) {
+        let mut transfers = Vec::new();
+        let mut remote_deletes = Vec::new();
+
+        let local_root = Path::new(&plan.local_path);
+        for planned in &plan.actions {
+            // Plans come back from the UI, so nothing in them is trusted.
+            if let Err(e) = ensure_inside(local_root, &local_root.join(&planned.path)).await {
+                result.errors.push(SyncFileError::new(&planned.path, &e));
+                continue;
+            }
+            match planned.action {
+                SyncAction::Upload | SyncAction::Download => transfers.push(planned),
+                SyncAction::DeleteRemote => remote_deletes.push(planned),
+                _ => {
+                    if let Err(e) = self
+                        .apply_local_action(plan, db.as_deref_mut(), planned, result)
+                        .await
+                    {
+                        result.errors.push(SyncFileError::new(&planned.path, &e));
+                    }
+                }
+            }
+        }
+
+        // Transfers run side by side; only their bookkeeping is sequential.
+        let track = db.is_some();
+        let transfers: Vec<_> = transfers
+            .into_iter()
+            .map(|planned| {
+                async move { (planned, self.transfer(plan, planned, track).await) }.boxed()
+            })
+            .collect();
+        let mut finished =
+            stream::iter(transfers).buffer_unordered(self.sync_concurrency.transfers.max(1));
+
+        while let Some((planned, outcome)) = finished.next().await {
+            match outcome {
+                Ok(record) => {
+                    if let (Some(db), Some(record)) = (db.as_deref_mut(), record) {
+                        db.records.insert(planned.path.clone(), record);
+                    }
+                    result.record(&planned.path, planned.action, planned.size);
+                }
+                Err(e) => result.errors.push(SyncFileError::new(&planned.path, &e)),
+            }
+        }
+
+        for batch in remote_deletes.chunks(DELETE_BATCH_SIZE) {
+            let keys: Vec<String> = batch
+                .iter()
+                .map(|planned| join_key(&plan.prefix, &planned.path))
+                .collect();
+
+            let deleted = match self.delete_objects(&plan.bucket, &keys).await {
+                Ok(deleted) => deleted,
+                Err(e) => {
+                    for planned in batch {
+                        result.errors.push(SyncFileError::new(&planned.path, &e));
+                    }
+                    continue;
+                }
+            };
+
+            let failed: HashMap<&str, &DeleteError> =
+                deleted.errors.iter().map(|e| (e.key.as_str(), e)).collect();
+            for (planned, key) in batch.iter().zip(&keys) {
+                if let Some(error) = failed.get(key.as_str()) {
+                    result.errors.push(SyncFileError {
+                        path: planned.path.clone(),
+                        message: error.message.clone(),
+                        code: error.code.clone(),
+                    });
+                    continue;
+                }
+                if let Some(db) = db.as_deref_mut() {
+                    db.records.remove(&planned.path);
+                }
+                result.record(&planned.path, planned.action, planned.size);
+            }
+        }
+    }
+
+    /// Uploads or downloads one planned file, returning the record both
+    /// sides now agree on when `track` is set.
+    async fn transfer(
+        &self,
+        plan: &SyncPlan,
+        planned: &PlannedAction,
+        track: bool,
+    ) -> Result<Option<SyncRecord>> {
+        let key = join_key(&plan.prefix, &planned.path);
+        let target = Path::new(&plan.local_path).join(&planned.path);
+
+        let attributes = TransferAttributes::of(plan);
+        let control = TransferControl::default();
+
+        let (local_md5, remote_etag) = if planned.action == SyncAction::Upload {
+            let etag = self
+                .upload_file_with(
+                    &plan.bucket,
+                    &key,
+                    &target.to_string_lossy(),
+                    attributes,
+                    &control,
+                )
+                .await?;
+            (planned.local_md5.clone(), etag)
+        } else {
+            self.download_object_with(
+                &plan.bucket,
+                &key,
+                None,
+                &target.to_string_lossy(),
+                attributes,
+                &control,
+            )
+            .await?;
+            (None, planned.remote_etag.clone())
+        };
+
+        if !track {
+            return Ok(None);
+        }
+        self.record_for(&target, local_md5, remote_etag)
+            .await
+            .map(Some)
+    }
+
+    /// Local deletes, conflicts and skips, which don't transfer anything
+    /// unless a conflict resolution calls for it.
+    async fn apply_local_action(
+        &self,
+        plan: &SyncPlan,
+        db: Option<&mut SyncDatabase>,
+        planned: &PlannedAction,
+        result: &mut SyncResult,
+    ) -> Result<()> {
+        let path = &planned.path;
+        let local_root = Path::new(&plan.local_path);
+        let target = local_root.join(path);
+
+        match planned.action {
+            SyncAction::DeleteLocal => {
+                if let Some(planned_md5) = &planned.local_md5 {
+                    let base = db.as_deref().and_then(|db| db.records.get(path));
+                    if !self
+                        .local_md5_since(&target, base)
+                        .await?
+                        .eq_ignore_ascii_case(planned_md5)
+                    {
+                        bail!("Changed since the sync was planned, so it wasn't deleted");
+                    }
+                }
+                tokio::fs::remove_file(&target)
+                    .await
+                    .context("Failed to delete local file")?;
+                if let Some(db) = db {
+                    db.records.remove(path);
+                }
+                result.record(path, planned.action, planned.size);
+            }
+            SyncAction::Conflict => {
+                let Some(conflict) = &planned.conflict else {
+                    return Ok(());
+                };
+                if let Some(db) = db {
+                    self.apply_resolution(
+                        &plan.bucket,
+                        &plan.prefix,
+                        local_root,
+                        db,
+                        conflict,
+                        planned.remote_etag.clone(),
+                        result,
+                    )
+                    .await?;
+                }
+                result.conflicts.push(conflict.clone());
+            }
+            SyncAction::Skip => {
+                let has_local = planned.local_md5.is_some();
+                let has_remote = planned.remote_etag.is_some();
+
+                if let Some(db) = db {
+                    if has_local && has_remote {
+                        // Both sides already agree; remember it.
+                        let record = self
+                            .record_for(
+                                &target,
+                                planned.local_md5.clone(),
+                                planned.remote_etag.clone(),
+                            )
+                            .await?;
+                        db.records.insert(path.clone(), record);
+                    } else if !has_local && !has_remote {
+                        // Gone from both sides since the last sync.
+                        db.records.remove(path);
+                    }
+                }
+
+                if has_local || has_remote {
+                    result.record(path, planned.action, planned.size);
+                }
+            }
+            SyncAction::Upload | SyncAction::Download | SyncAction::DeleteRemote => {}
+        }
+
+        Ok(())
+    }
+
+    /// Settles conflicts a bidirectional sync left for the user, checking
+    /// both sides again since they may have moved on in the meantime.
+    pub async fn resolve_sync_conflicts(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        local_folder: &str,
+        resolutions: &[(String, ConflictResolution)],
+    ) -> Result<SyncResult> {
+        let local_root = Path::new(local_folder);
+        let mut db = SyncDatabase::load(&self.profile_id, bucket, prefix, local_folder)?;
+        let mut result = SyncResult::default();
+
+        let outcome = async {
+            for (path, resolution) in resolutions {
+                let target = local_root.join(path);
+                let local_path = tokio::fs::try_exists(&target)
+                    .await
+                    .unwrap_or(false)
+                    .then_some(target.as_path());
+                let remote = self
+                    .head_object_info(bucket, &join_key(prefix, path))
+                    .await?;
+                if local_path.is_none() && remote.is_none() {
+                    db.records.remove(path);
+                    continue;
+                }
+
+                let mut conflict = local_conflict(path.clone(), local_path).await?;
+                if let Some(obj) = &remote {
+                    conflict.remote_size = Some(obj.size);
+                    conflict.remote_modified = obj.last_modified;
+                }
+                conflict.resolution = Some(*resolution);
+                self.apply_resolution(
+                    bucket,
+                    prefix,
+                    local_root,
+                    &mut db,
+                    &conflict,
+                    remote.and_then(|o| o.etag),
+                    &mut result,
+                )
+                .await?;
+                result.conflicts.push(conflict);
+            }
+            Ok::<(), anyhow::Error>(())
+        }
+        .await;
+
+        db.save()?;
+        outcome.map(|_| result)
+    }
+
+    /// Carries out the resolution chosen for a conflict, if any, and records
+    /// the agreed state of every path it touched.
+    #[allow(clippy::too_many_arguments)]
+    async fn apply_resolution(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        local_root: &Path,
+        db: &mut SyncDatabase,
+        conflict: &SyncConflict,
+        remote_etag: Option<String>,
+        result: &mut SyncResult,
+    ) -> Result<()> {
+        let Some(resolution) = conflict.resolution else {
+            return Ok(());
+        };
+
+        let path = &conflict.path;
+        let key = join_key(prefix, path);
+        let target = local_root.join(path);
+        // Paths to resolve come from the UI.
+        ensure_inside(local_root, &target).await?;
+        let has_local = conflict.local_size.is_some();
+        let has_remote = conflict.remote_size.is_some();
+        let local_size = conflict.local_size.unwrap_or(0);
+        let remote_size = conflict.remote_size.unwrap_or(0).max(0) as u64;
+
+        // Keeping both only makes sense when both exist; against a deletion
+        // it means keeping the edited side.
+        let resolution = match resolution {
+            ConflictResolution::Both if !has_local => ConflictResolution::Remote,
+            ConflictResolution::Both if !has_remote => ConflictResolution::Local,
+            other => other,
+        };
+
+        match resolution {
+            ConflictResolution::Local if has_local => {
+                let etag = self
+                    .upload_file(bucket, &key, &target.to_string_lossy())
+                    .await?;
+                let record = self.record_for(&target, None, etag).await?;
+                db.records.insert(path.clone(), record);
+                result.record(path, SyncAction::Upload, local_size);
+            }
+            ConflictResolution::Local => {
+                self.delete_object(bucket, &key).await?;
+                db.records.remove(path);
+                result.record(path, SyncAction::DeleteRemote, remote_size);
+            }
+            ConflictResolution::Remote if has_remote => {
+                self.download_object_within(bucket, &key, &target.to_string_lossy(), local_root)
+                    .await?;
+                let record = self.record_for(&target, None, remote_etag).await?;
+                db.records.insert(path.clone(), record);
+                result.record(path, SyncAction::Download, remote_size);
+            }
+            ConflictResolution::Remote => {
+                tokio::fs::remove_file(&target)
+                    .await
+                    .context("Failed to delete local file")?;
+                db.records.remove(path);
+                result.record(path, SyncAction::DeleteLocal, local_size);
+            }
+            ConflictResolution::Both => {
+                let copy_path = conflict_copy_name(path, Utc::now());
+                let copy_key = join_key(prefix, &copy_path);
+                let copy_target = local_root.join(&copy_path);
+
+                // The older side moves aside under the conflict name.
+                let copy_size = if conflict.local_modified < conflict.remote_modified {
+                    tokio::fs::rename(&target, &copy_target)
+                        .await
+                        .context("Failed to rename conflicting file")?;
+                    self.download_object_within(
+                        bucket,
+                        &key,
+                        &target.to_string_lossy(),
+                        local_root,
+                    )
+                    .await?;
+                    let record = self.record_for(&target, None, remote_etag).await?;
+                    db.records.insert(path.clone(), record);
+                    result.record(path, SyncAction::Download, remote_size);
+                    local_size
+                } else {
+                    self.download_object_within(
+                        bucket,
+                        &key,
+                        &copy_target.to_string_lossy(),
+                        local_root,
+                    )
+                    .await?;
+                    let etag = self
+                        .upload_file(bucket, &key, &target.to_string_lossy())
+                        .await?;
+                    let record = self.record_for(&target, None, etag).await?;
+                    db.records.insert(path.clone(), record);
+                    result.record(&copy_path, SyncAction::Download, remote_size);
+                    result.record(path, SyncAction::Upload, local_size);
+                    remote_size
+                };
+
+                let etag = self
+                    .upload_file(bucket, &copy_key, &copy_target.to_string_lossy())
+                    .await?;
+                let record = self.record_for(&copy_target, None, etag).await?;
+                db.records.insert(copy_path.clone(), record);
+                result.record(&copy_path, SyncAction::Upload, copy_size);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Size, modification time and ETag of an object, or `None` if it
+    /// doesn't exist.
+    pub async fn head_object_info(&self, bucket: &str, key: &str) -> Result<Option<ObjectInfo>> {
+        match self
+            .client
+            .head_object()
+            .bucket(bucket)
+            .key(key)
+            .send()
+            .await
+        {
+            Ok(resp) => Ok(Some(ObjectInfo {
+                key: key.to_string(),
+                size: resp.content_length().unwrap_or(0),
+                last_modified: resp
+                    .last_modified()
+                    .and_then(|dt| DateTime::from_timestamp(dt.secs(), dt.subsec_nanos())),
+                etag: resp.e_tag().map(|s| s.to_string()),
+                is_folder: key.ends_with('/'),
+            })),
+            Err(err) if err.as_service_error().is_some_and(|e| e.is_not_found()) => Ok(None),
+            Err(err) => Err(err).context("Failed to get object metadata"),
+        }
+    }
+
+    /// MD5 of a local file, reusing the recorded hash when size and mtime
+    /// show the file hasn't been touched since the last sync.
+    pub async fn local_md5_since(&self, path: &Path, base: Option<&SyncRecord>) -> Result<String> {
+        if let Some(base) = base {
+            let metadata = tokio::fs::metadata(path)
+                .await
+                .context("Failed to read file metadata")?;
+            if metadata.len() == base.local_size && mtime_millis(&metadata) == base.local_mtime {
+                return Ok(base.local_md5.clone());
+            }
+        }
+
+        self.compute_local_etag(path).await
+    }
+
+    /// Snapshot of a path both sides now agree on. `local_md5` is hashed
+    /// from disk when the caller doesn't already know it.
+    pub async fn record_for(
+        &self,
+        path: &Path,
+        local_md5: Option<String>,
+        remote_etag: Option<String>,
+    ) -> Result<SyncRecord> {
+        let metadata = tokio::fs::metadata(path)
+            .await
+            .context("Failed to read file metadata")?;
+        let local_md5 = match local_md5 {
+            Some(md5) => md5,
+            None => self.compute_local_etag(path).await?,
+        };
+        Ok(SyncRecord {
+            local_md5,
+            local_size: metadata.len(),
+            local_mtime: mtime_millis(&metadata),
+            remote_etag: remote_etag.unwrap_or_default(),
+        })
+    }
+
+    /// Lists every object under `prefix`, descending into "subfolders"
+    /// instead of stopping at the first `/`.
+    pub async fn list_objects_recursive(
+        &self,
+        bucket: &str,
+        prefix: &str,
+    ) -> Result<Vec<ObjectInfo>> {
+        let mut all_objects = Vec::new();
+        let mut continuation_token: Option<String> = None;
 
         loop {
-            let bytes_read = file.read(&mut buffer).await.context("Failed to read file")?;
-            if bytes_read == 0 {
+            let mut req = self
+                .client
+                .list_objects_v2()
+                .bucket(bucket)
+                .prefix(prefix)
+                .max_keys(1000);
+
+            if let Some(token) = &continuation_token {
+                req = req.continuation_token(token);
+            }
+
+            let resp = req.send().await.context("Failed to list objects")?;
+            all_objects.extend(resp.contents().iter().map(object_info));
+
+            if !resp.is_truncated().unwrap_or(false) {
+                break;
+            }
+
+            continuation_token = resp.next_continuation_token().map(|s| s.to_string());
+            if continuation_token.is_none() {
                 break;
             }
-            hasher.update(&buffer[..bytes_read]);
         }
 
-        let hash = hasher.finalize();
-        Ok(format!("{:x}", hash))
+        Ok(all_objects)
+    }
+
+    /// Whether the object at `key` already holds the content of
+    /// `local_path`. The file is only hashed if it changed since it was
+    /// last hashed.
+    pub async fn object_matches_file(
+        &self,
+        bucket: &str,
+        key: &str,
+        local_path: &Path,
+    ) -> Result<bool> {
+        let Some(remote) = self.head_object_info(bucket, key).await? else {
+            return Ok(false);
+        };
+        let local_md5 = self.compute_local_etag(local_path).await?;
+        self.matches_remote(bucket, key, local_path, &local_md5, &remote)
+            .await
+    }
+
+    /// Compares a local file with an object by size and, for
+    /// `CompareMode::SizeMtime`, modification time. Only objects the same
+    /// size as the file need a HEAD request.
+    async fn metadata_matches_remote(
+        &self,
+        bucket: &str,
+        local_path: &Path,
+        remote: &ObjectInfo,
+        mode: CompareMode,
+    ) -> Result<bool> {
+        let metadata = tokio::fs::metadata(local_path)
+            .await
+            .context("Failed to read file metadata")?;
+        let local_modified = DateTime::<Utc>::from(
+            metadata
+                .modified()
+                .context("Failed to read file modification time")?,
+        );
+
+        let remote_mtime = if mode == CompareMode::SizeMtime
+            && i64::try_from(metadata.len()).ok() == Some(remote.size)
+        {
+            self.remote_mtime(bucket, remote).await?
+        } else {
+            RemoteMtime::Unknown
+        };
+
+        Ok(mode.metadata_matches(metadata.len(), local_modified, remote.size, remote_mtime))
+    }
+
+    /// The modification time recorded at upload, falling back to when the
+    /// object was written.
+    async fn remote_mtime(&self, bucket: &str, remote: &ObjectInfo) -> Result<RemoteMtime> {
+        let head = self
+            .client
+            .head_object()
+            .bucket(bucket)
+            .key(&remote.key)
+            .send()
+            .await
+            .context("Failed to get object metadata")?;
+
+        if let Some(recorded) = recorded_mtime(head.metadata()) {
+            return Ok(RemoteMtime::Recorded(recorded));
+        }
+        Ok(remote
+            .last_modified
+            .map_or(RemoteMtime::Unknown, RemoteMtime::LastModified))
+    }
+
+    /// Whether a local file holds the same bytes as a remote object. Goes
+    /// beyond `compare_checksums` for multipart ETags by recomputing them
+    /// locally, and falls back to size and modification time for objects
+    /// whose ETag isn't derived from the content (SSE-KMS, SSE-C) or whose
+    /// part size can't be worked out.
+    async fn matches_remote(
+        &self,
+        bucket: &str,
+        key: &str,
+        local_path: &Path,
+        local_md5: &str,
+        remote: &ObjectInfo,
+    ) -> Result<bool> {
+        let metadata = tokio::fs::metadata(local_path)
+            .await
+            .context("Failed to read file metadata")?;
+        let size = metadata.len();
+        if i64::try_from(size).ok() != Some(remote.size) {
+            return Ok(false);
+        }
+
+        let Some(etag) = remote.etag.as_deref() else {
+            return Ok(false);
+        };
+        if compare_checksums(local_md5, etag) {
+            return Ok(true);
+        }
+
+        // Same size but no match: either the content changed or the ETag
+        // isn't something we can reproduce. The object's headers tell which,
+        // and may record the part size, so they come before any rehashing.
+        let head = self
+            .client
+            .head_object()
+            .bucket(bucket)
+            .key(key)
+            .send()
+            .await
+            .context("Failed to get object metadata")?;
+
+        let opaque_etag = matches!(
+            head.server_side_encryption(),
+            Some(ServerSideEncryption::AwsKms | ServerSideEncryption::AwsKmsDsse)
+        ) || head.sse_customer_algorithm().is_some();
+
+        if !opaque_etag {
+            let Some(parts) = multipart_part_count(etag) else {
+                return Ok(false);
+            };
+
+            let stored_part_size = head
+                .metadata()
+                .and_then(|m| m.get(PART_SIZE_METADATA))
+                .and_then(|v| v.parse::<u64>().ok())
+                .filter(|&part_size| part_size > 0);
+            if let Some(part_size) = stored_part_size {
+                let local_etag = self.compute_multipart_etag(local_path, part_size).await?;
+                return Ok(compare_checksums(&local_etag, etag));
+            }
+
+            let preferred = [self.multipart.part_size_for(size)];
+            for part_size in candidate_part_sizes(size, parts, &preferred) {
+                let local_etag = self.compute_multipart_etag(local_path, part_size).await?;
+                if compare_checksums(&local_etag, etag) {
+                    return Ok(true);
+                }
+            }
+        }
+
+        // Unchanged if the object was written after the file last changed.
+        let local_modified = metadata.modified().ok().map(DateTime::<Utc>::from);
+        Ok(matches!(
+            (remote.last_modified, local_modified),
+            (Some(remote), Some(local)) if remote >= local
+        ))
+    }
+
+    /// The ETag S3 gives a multipart upload of `path` split into
+    /// `part_size` parts, from the hash cache if the file hasn't changed.
+    async fn compute_multipart_etag(&self, path: &Path, part_size: u64) -> Result<String> {
+        let stat = local_stat(path).await?;
+        if let Some(etag) =
+            hash_cache::with_shared(|cache| cache.multipart_etag(path, &stat, part_size))
+        {
+            return Ok(etag);
+        }
+
+        let etag = hash_multipart_etag(path, part_size).await?;
+        if local_stat(path).await? == stat {
+            hash_cache::with_shared(|cache| {
+                cache.insert_multipart_etag(path, &stat, part_size, etag.clone())
+            });
+        }
+        Ok(etag)
+    }
+
+    /// MD5 of `path`, from the hash cache if the file hasn't changed.
+    async fn compute_local_etag(&self, path: &Path) -> Result<String> {
+        let stat = local_stat(path).await?;
+        if let Some(md5) = hash_cache::with_shared(|cache| cache.md5(path, &stat)) {
+            return Ok(md5);
+        }
+
+        let md5 = hash_file_md5(path).await?;
+        // Only cache a hash of a file that didn't change while being read.
+        if local_stat(path).await? == stat {
+            hash_cache::with_shared(|cache| cache.insert_md5(path, &stat, md5.clone()));
+        }
+        Ok(md5)
     }
 
     pub async fn get_object_metadata(
@@ -635,3 +3818,517 @@ impl S3Client {
         })
     }
 }
+
+/// The MD5 of the binary MD5s of each `part_size` part, plus the part count.
+async fn hash_multipart_etag(path: &Path, part_size: u64) -> Result<String> {
+    let mut file = File::open(path).await.context("Failed to open file")?;
+    let mut part_digests = Vec::new();
+    let mut part_hasher = Md5::new();
+    let mut part_len = 0u64;
+    let mut parts = 0u64;
+    let mut buffer = vec![0u8; 64 * 1024];
+
+    loop {
+        let want = buffer.len().min((part_size - part_len) as usize);
+        let bytes_read = file
+            .read(&mut buffer[..want])
+            .await
+            .context("Failed to read file")?;
+        if bytes_read == 0 {
+            break;
+        }
+        part_hasher.update(&buffer[..bytes_read]);
+        part_len += bytes_read as u64;
+
+        if part_len == part_size {
+            part_digests.extend_from_slice(&part_hasher.finalize_reset());
+            part_len = 0;
+            parts += 1;
+        }
+    }
+
+    if part_len > 0 || parts == 0 {
+        part_digests.extend_from_slice(&part_hasher.finalize());
+        parts += 1;
+    }
+
+    Ok(format!("{:x}-{}", Md5::digest(&part_digests), parts))
+}
+
+async fn hash_file_md5(path: &Path) -> Result<String> {
+    let mut file = File::open(path).await.context("Failed to open file")?;
+    let mut hasher = Md5::new();
+    let mut buffer = vec![0u8; 8192];
+
+    loop {
+        let bytes_read = file.read(&mut buffer).await.context("Failed to read file")?;
+        if bytes_read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..bytes_read]);
+    }
+
+    let hash = hasher.finalize();
+    Ok(format!("{:x}", hash))
+}
+
+async fn local_stat(path: &Path) -> Result<FileStat> {
+    let metadata = tokio::fs::metadata(path)
+        .await
+        .context("Failed to read file metadata")?;
+    Ok(FileStat::from_metadata(&metadata))
+}
+
+/// Plans every path with up to `concurrency` running at once, sorted by
+/// path. A path that fails is recorded in `errors` and left out.
+async fn plan_each<'a, F, Fut>(
+    paths: impl IntoIterator<Item = &'a String>,
+    concurrency: usize,
+    errors: &mut Vec<SyncFileError>,
+    plan: F,
+) -> Vec<PlannedAction>
+where
+    F: Fn(&'a String) -> Fut,
+    Fut: Future<Output = Result<PlannedAction>> + Send + 'a,
+{
+    // Built up front and boxed so no closure is held across an await,
+    // which keeps the future `Send` for callers inside `tokio::spawn`.
+    let pending: Vec<_> = paths
+        .into_iter()
+        .map(|path| {
+            let planned = plan(path);
+            async move { (path, planned.await) }.boxed()
+        })
+        .collect();
+    let results: Vec<(&String, Result<PlannedAction>)> = stream::iter(pending)
+        .buffer_unordered(concurrency.max(1))
+        .collect()
+        .await;
+
+    let mut actions = Vec::new();
+    for (path, result) in results {
+        match result {
+            Ok(planned) => actions.push(planned),
+            Err(e) => errors.push(SyncFileError::new(path, &e)),
+        }
+    }
+    actions.sort_by(|a, b| a.path.cmp(&b.path));
+    errors.sort_by(|a, b| a.path.cmp(&b.path));
+    actions
+}
+
+/// A conflict for `path` filled in with the local side; the caller adds
+/// what it knows about the remote side.
+async fn local_conflict(path: String, local_path: Option<&Path>) -> Result<SyncConflict> {
+    let metadata = match local_path {
+        Some(local_path) => Some(
+            tokio::fs::metadata(local_path)
+                .await
+                .context("Failed to read file metadata")?,
+        ),
+        None => None,
+    };
+
+    Ok(SyncConflict {
+        path,
+        local_size: metadata.as_ref().map(|m| m.len()),
+        local_modified: metadata
+            .as_ref()
+            .and_then(|m| m.modified().ok())
+            .map(DateTime::<Utc>::from),
+        remote_size: None,
+        remote_modified: None,
+        resolution: None,
+    })
+}
+
+/// Progress of an interrupted download, stored next to its partial file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ResumeMarker {
+    bucket: String,
+    key: String,
+    etag: String,
+    offset: u64,
+}
+
+/// Path of the temp file a download of `path` is written to before it is
+/// renamed into place.
+fn partial_download_path(path: &Path) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.s3gui-part", file_name))
+}
+
+fn resume_marker_path(path: &Path) -> PathBuf {
+    let mut marker = partial_download_path(path).into_os_string();
+    marker.push(".json");
+    PathBuf::from(marker)
+}
+
+/// True for the temp and marker files left behind by interrupted downloads,
+/// which must never be uploaded.
+pub fn is_partial_download(path: &Path) -> bool {
+    path.file_name()
+        .map(|n| n.to_string_lossy())
+        .map(|n| n.ends_with(".s3gui-part") || n.ends_with(".s3gui-part.json"))
+        .unwrap_or(false)
+}
+
+/// Removes the temp file and resume marker of an abandoned download.
+pub async fn discard_partial_download(local_path: &str) {
+    let path = Path::new(local_path);
+    let _ = tokio::fs::remove_file(partial_download_path(path)).await;
+    let _ = tokio::fs::remove_file(resume_marker_path(path)).await;
+}
+
+async fn read_resume_marker(
+    marker_path: &Path,
+    part_path: &Path,
+    bucket: &str,
+    key: &str,
+) -> Option<ResumeMarker> {
+    let content = tokio::fs::read_to_string(marker_path).await.ok()?;
+    let mut marker: ResumeMarker = serde_json::from_str(&content).ok()?;
+    if marker.bucket != bucket || marker.key != key {
+        return None;
+    }
+
+    // Only trust bytes that actually made it to disk.
+    let on_disk = tokio::fs::metadata(part_path).await.ok()?.len();
+    marker.offset = marker.offset.min(on_disk);
+    (marker.offset > 0).then_some(marker)
+}
+
+async fn write_resume_marker(marker_path: &Path, marker: &ResumeMarker) -> Result<()> {
+    let content = serde_json::to_string(marker)?;
+    tokio::fs::write(marker_path, content)
+        .await
+        .context("Failed to write resume marker")
+}
+
+/// Writes `body` into `path` starting at the marker's offset (or from zero
+/// without a marker), checkpointing the marker as data reaches disk.
+async fn write_body_to_file(
+    mut body: ByteStream,
+    path: &Path,
+    mut marker: Option<ResumeMarker>,
+    marker_path: &Path,
+    control: &TransferControl,
+) -> Result<()> {
+    let offset = marker.as_ref().map(|m| m.offset).unwrap_or(0);
+
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(offset == 0)
+        .open(path)
+        .await
+        .context("Failed to open temp file")?;
+    if offset > 0 {
+        file.set_len(offset)
+            .await
+            .context("Failed to truncate temp file")?;
+        file.seek(std::io::SeekFrom::Start(offset))
+            .await
+            .context("Failed to seek temp file")?;
+    }
+
+    if let Some(marker) = &marker {
+        write_resume_marker(marker_path, marker).await?;
+    }
+
+    let mut writer = BufWriter::with_capacity(DOWNLOAD_BUFFER_SIZE, file);
+    let mut written = offset;
+    let mut checkpointed = offset;
+
+    while let Some(chunk) = body.try_next().await.context("Failed to read body")? {
+        writer
+            .write_all(&chunk)
+            .await
+            .context("Failed to write file")?;
+        written += chunk.len() as u64;
+        control.advance(chunk.len() as u64);
+
+        if let Some(marker) = marker.as_mut() {
+            if written - checkpointed >= RESUME_CHECKPOINT_BYTES {
+                writer.flush().await.context("Failed to write file")?;
+                writer
+                    .get_ref()
+                    .sync_data()
+                    .await
+                    .context("Failed to flush file to disk")?;
+                marker.offset = written;
+                write_resume_marker(marker_path, marker).await?;
+                checkpointed = written;
+            }
+        }
+
+        if control.is_cancelled() {
+            // Leave whatever has been written resumable before bailing out.
+            writer.flush().await.context("Failed to write file")?;
+            if let Some(marker) = marker.as_mut() {
+                marker.offset = written;
+                write_resume_marker(marker_path, marker).await?;
+            }
+            control.check_cancelled()?;
+        }
+    }
+
+    writer.flush().await.context("Failed to write file")?;
+    writer
+        .into_inner()
+        .sync_all()
+        .await
+        .context("Failed to flush file to disk")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIB: u64 = 1024 * 1024;
+
+    #[test]
+    fn test_delete_cap() {
+        assert!(check_delete_cap(0, 0, 0).is_ok());
+        assert!(check_delete_cap(5, 10, 50).is_ok());
+        assert!(check_delete_cap(6, 10, 50).is_err());
+        assert!(check_delete_cap(3, 3, 100).is_ok());
+    }
+
+    #[test]
+    fn test_part_size_respects_minimum() {
+        let config = MultipartConfig {
+            part_size: MIB,
+            ..MultipartConfig::default()
+        };
+        assert_eq!(config.part_size_for(100 * MIB), MIN_PART_SIZE);
+    }
+
+    #[test]
+    fn test_part_size_grows_for_huge_files() {
+        let config = MultipartConfig::default();
+        let file_size = 500 * 1024 * MIB;
+        let part_size = config.part_size_for(file_size);
+        assert!((file_size + part_size - 1) / part_size <= MAX_UPLOAD_PARTS);
+    }
+
+    #[test]
+    fn test_partial_downloads_are_recognised() {
+        let path = Path::new("/data/video.mp4");
+        assert!(is_partial_download(&partial_download_path(path)));
+        assert!(is_partial_download(&resume_marker_path(path)));
+        assert!(!is_partial_download(path));
+    }
+
+    #[test]
+    fn test_partial_download_path_is_hidden_sibling() {
+        let path = Path::new("/data/reports/q1.csv");
+        assert_eq!(
+            partial_download_path(path),
+            PathBuf::from("/data/reports/.q1.csv.s3gui-part")
+        );
+    }
+
+    #[test]
+    fn test_copy_source_encodes_key_but_not_slashes() {
+        assert_eq!(
+            copy_source("media", "photos/2024 trip/café+1.jpg", None),
+            "media/photos/2024%20trip/caf%C3%A9%2B1.jpg"
+        );
+        assert_eq!(
+            copy_source("media", "a b.txt", Some("3/L4kqtJl")),
+            "media/a%20b.txt?versionId=3%2FL4kqtJl"
+        );
+    }
+
+    #[test]
+    fn test_tagging_query_is_sorted_and_encoded() {
+        let tags = HashMap::from([
+            ("team".to_string(), "data & ml".to_string()),
+            ("env".to_string(), "prod".to_string()),
+        ]);
+        assert_eq!(tagging_query(&tags), "env=prod&team=data%20%26%20ml");
+    }
+
+    #[test]
+    fn test_versions_are_sorted_newest_first_per_key() {
+        let version = |key: &str, id: &str, secs: i64, is_latest: bool| ObjectVersion {
+            key: key.to_string(),
+            version_id: id.to_string(),
+            is_latest,
+            is_delete_marker: false,
+            size: 0,
+            last_modified: DateTime::from_timestamp(secs, 0),
+            etag: None,
+        };
+        let mut versions = vec![
+            version("b.txt", "b1", 100, true),
+            version("a.txt", "a1", 100, false),
+            version("a.txt", "a3", 300, true),
+            version("a.txt", "a2", 200, false),
+        ];
+
+        sort_versions(&mut versions);
+
+        let ids: Vec<&str> = versions.iter().map(|v| v.version_id.as_str()).collect();
+        assert_eq!(ids, ["a3", "a2", "a1", "b1"]);
+    }
+
+    #[test]
+    fn test_link_targets_must_stay_inside_the_sync_folder() {
+        let root = Path::new("/home/me/sync");
+        let link = root.join("docs/latest");
+
+        assert!(link_stays_inside(root, &link, Path::new("v2/notes.txt")));
+        assert!(link_stays_inside(root, &link, Path::new("../photos")));
+        assert!(!link_stays_inside(root, &link, Path::new("../../..")));
+        assert!(!link_stays_inside(root, &link, Path::new("/home/me/.ssh")));
+
+        let elsewhere = Path::new("/elsewhere/link");
+        assert!(!link_stays_inside(root, elsewhere, Path::new("a")));
+    }
+
+    #[tokio::test]
+    async fn test_download_paths_must_stay_inside_the_sync_folder() {
+        let root = std::env::temp_dir();
+
+        let climbing = root.join("a/../../b.txt");
+
+        assert!(ensure_inside(&root, &root.join("a/b.txt")).await.is_ok());
+        assert!(ensure_inside(&root, &climbing).await.is_err());
+        assert!(ensure_inside(&root, Path::new("/b.txt")).await.is_err());
+    }
+
+    async fn test_client(endpoint: Option<&str>, access_key_id: &str) -> S3Client {
+        S3ClientBuilder::new(Profile {
+            id: "test".to_string(),
+            name: "test".to_string(),
+            access_key_id: access_key_id.to_string(),
+            secret_access_key: "secret".to_string(),
+            region: "us-east-1".to_string(),
+            endpoint: endpoint.map(str::to_string),
+            path_style: true,
+            signature_version: SignatureVersion::V4,
+        })
+        .build()
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_deletes_are_sent_in_batches_of_a_thousand() {
+        let keys: Vec<String> = (0..2500).map(|i| format!("file-{}", i)).collect();
+        let objects = object_identifiers(&keys);
+        let batches = std::sync::Mutex::new(Vec::new());
+
+        let result = delete_each(
+            &objects,
+            |_, _| {},
+            |batch| {
+                batches.lock().unwrap().push(batch.len());
+                async move {
+                    Ok(DeleteObjectsResult {
+                        deleted: batch.len() as u64,
+                        errors: vec![],
+                    })
+                }
+            },
+        )
+        .await;
+
+        assert_eq!(batches.into_inner().unwrap(), [1000, 1000, 500]);
+        assert_eq!(result.deleted, 2500);
+        assert!(result.errors.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_delete_prefix_refuses_an_empty_prefix() {
+        let client = test_client(None, "key").await;
+
+        for prefix in ["", "/"] {
+            let err = client
+                .delete_prefix("bucket", prefix, |_, _| {})
+                .await
+                .unwrap_err();
+            assert!(err.to_string().contains("Refusing to delete everything"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_server_side_copy_needs_the_same_endpoint_and_keys() {
+        let minio = Some("http://localhost:9000");
+        let client = test_client(minio, "key").await;
+
+        let same = test_client(minio, "key").await;
+        let other_endpoint = test_client(Some("http://localhost:9001"), "key").await;
+        let aws = test_client(None, "key").await;
+        let other_keys = test_client(minio, "other").await;
+
+        assert!(client.shares_account_with(&same));
+        assert!(!client.shares_account_with(&other_endpoint));
+        assert!(!client.shares_account_with(&aws));
+        assert!(!client.shares_account_with(&other_keys));
+    }
+
+    #[tokio::test]
+    async fn test_a_file_that_fails_to_plan_is_left_out() {
+        let paths = ["a.txt", "b.txt", "c.txt"].map(String::from);
+        let mut errors = Vec::new();
+
+        let actions = plan_each(&paths, 2, &mut errors, |path| async move {
+            if path == "b.txt" {
+                bail!("Permission denied");
+            }
+            Ok(PlannedAction::new(path, SyncAction::Skip, 0, "Unchanged"))
+        })
+        .await;
+
+        let planned: Vec<&str> = actions.iter().map(|a| a.path.as_str()).collect();
+        assert_eq!(planned, ["a.txt", "c.txt"]);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path, "b.txt");
+    }
+
+    #[tokio::test]
+    async fn test_a_file_that_fails_doesnt_stop_the_run() {
+        let root = std::env::temp_dir().join(format!("s3gui-execute-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        for name in ["a.txt", "changed.txt", "c.txt"] {
+            std::fs::write(root.join(name), name).unwrap();
+        }
+
+        let mut changed = PlannedAction::new("changed.txt", SyncAction::DeleteLocal, 11, "");
+        changed.local_md5 = Some("0".repeat(32));
+        let plan = SyncPlan {
+            bucket: "bucket".to_string(),
+            prefix: String::new(),
+            local_path: root.to_string_lossy().to_string(),
+            direction: SyncDirection::RemoteToLocal,
+            actions: vec![
+                PlannedAction::new("a.txt", SyncAction::DeleteLocal, 5, ""),
+                changed,
+                PlannedAction::new("c.txt", SyncAction::DeleteLocal, 5, ""),
+            ],
+            errors: vec![],
+            symlinks: SymlinkPolicy::default(),
+            preserve_permissions: false,
+        };
+
+        let client = test_client(None, "key").await;
+        let mut result = SyncResult::default();
+        client.execute_actions(&plan, None, &mut result).await;
+
+        assert_eq!(result.deleted, 2);
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].path, "changed.txt");
+        assert!(root.join("changed.txt").exists());
+        assert!(!root.join("a.txt").exists() && !root.join("c.txt").exists());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,273 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+use cron::Schedule;
+use tauri::{AppHandle, Emitter};
+use thiserror::Error;
+use tokio::sync::RwLock;
+
+use crate::config::{self, ConfigError, SyncJob};
+use crate::run_log::{RunLog, RunTrigger, SyncRun};
+use crate::s3_client::{self, SyncResult};
+
+/// How often the scheduler looks for jobs that are due.
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Error, Debug)]
+pub enum ScheduleError {
+    #[error("Config error: {0}")]
+    Config(#[from] ConfigError),
+    #[error("Sync job not found: {0}")]
+    NotFound(String),
+    #[error("Invalid schedule {expression:?}: {message}")]
+    InvalidSchedule { expression: String, message: String },
+    #[error("Sync job is already running: {0}")]
+    AlreadyRunning(String),
+}
+
+pub type Result<T> = std::result::Result<T, ScheduleError>;
+
+/// Parses a cron expression. The usual five fields (minute, hour, day of
+/// month, month, day of week) are accepted, with days numbered the
+/// crontab way from 0 or 7 for Sunday, as well as the six or seven field
+/// form that starts with seconds and numbers days from 1 for Sunday.
+pub fn parse_schedule(expression: &str) -> Result<Schedule> {
+    let expression = expression.trim();
+    let fields: Vec<&str> = expression.split_whitespace().collect();
+    let full = if let [minute, hour, day, month, weekday] = fields[..] {
+        format!(
+            "0 {} {} {} {} {}",
+            minute,
+            hour,
+            day,
+            month,
+            crontab_weekdays(weekday)
+        )
+    } else {
+        expression.to_string()
+    };
+
+    Schedule::from_str(&full).map_err(|e| ScheduleError::InvalidSchedule {
+        expression: expression.to_string(),
+        message: e.to_string(),
+    })
+}
+
+/// Renumbers a crontab day-of-week field (0-6 from Sunday, 7 also Sunday)
+/// for the cron crate, which counts 1-7 from Sunday. Names and anything
+/// that isn't a day number are left for the crate to accept or reject.
+fn crontab_weekdays(field: &str) -> String {
+    let day = |token: &str| token.parse::<u32>().ok().filter(|n| *n <= 7);
+    let renumber = |token: &str| match day(token) {
+        Some(n) => (n % 7 + 1).to_string(),
+        None => token.to_string(),
+    };
+
+    let mut items = Vec::new();
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (range, Some(step)),
+            None => (item, None),
+        };
+        let step_suffix = step.map(|step| format!("/{}", step)).unwrap_or_default();
+
+        match range.split_once('-') {
+            // A range running up to Sunday as 7, like 5-7, would wrap round
+            // once Sunday becomes 1, so Sunday is listed on its own.
+            Some((start, end)) if day(end) == Some(7) && day(start).is_some_and(|n| n > 0) => {
+                let start = day(start).unwrap_or_default();
+                items.push(format!("{}-7{}", start + 1, step_suffix));
+                let every = step.and_then(|step| step.parse::<u32>().ok()).unwrap_or(1);
+                if every > 0 && (7 - start) % every == 0 {
+                    items.push("1".to_string());
+                }
+            }
+            Some((start, end)) => {
+                items.push(format!(
+                    "{}-{}{}",
+                    renumber(start),
+                    renumber(end),
+                    step_suffix
+                ));
+            }
+            None => items.push(format!("{}{}", renumber(range), step_suffix)),
+        }
+    }
+    items.join(",")
+}
+
+/// Whether `schedule` has a run after `since` that is no later than `now`.
+fn is_due(schedule: &Schedule, since: DateTime<Local>, now: DateTime<Local>) -> bool {
+    schedule
+        .after(&since)
+        .next()
+        .is_some_and(|next| next <= now)
+}
+
+/// Runs the sync jobs saved in the config when their schedule comes up or
+/// when asked to, recording each run in the run log.
+#[derive(Clone)]
+pub struct Scheduler {
+    run_log: RunLog,
+    running: Arc<RwLock<HashSet<String>>>,
+    app_handle: AppHandle,
+}
+
+impl Scheduler {
+    pub fn new(app_handle: AppHandle, run_log: RunLog) -> Self {
+        Self {
+            run_log,
+            running: Arc::new(RwLock::new(HashSet::new())),
+            app_handle,
+        }
+    }
+
+    /// Starts due jobs until the app exits. Runs that came up while the
+    /// app was closed are skipped rather than run late.
+    pub async fn run(&self) {
+        let mut last_check = Local::now();
+
+        loop {
+            tokio::time::sleep(CHECK_INTERVAL).await;
+            let now = Local::now();
+
+            let jobs = match config::load_config() {
+                Ok(config) => config.sync_jobs,
+                Err(_) => continue,
+            };
+
+            for job in jobs {
+                let due = job
+                    .schedule
+                    .as_deref()
+                    .and_then(|expression| parse_schedule(expression).ok())
+                    .is_some_and(|schedule| is_due(&schedule, last_check, now));
+                if !due {
+                    continue;
+                }
+
+                let scheduler = self.clone();
+                tokio::spawn(async move {
+                    let _ = scheduler.run_job(job, RunTrigger::Schedule).await;
+                });
+            }
+
+            last_check = now;
+        }
+    }
+
+    /// Runs a saved job now and waits for it to finish.
+    pub async fn run_job_now(&self, job_id: &str) -> Result<SyncRun> {
+        let job = config::load_config()?
+            .sync_jobs
+            .into_iter()
+            .find(|job| job.id == job_id)
+            .ok_or_else(|| ScheduleError::NotFound(job_id.to_string()))?;
+
+        self.run_job(job, RunTrigger::Manual).await
+    }
+
+    async fn run_job(&self, job: SyncJob, trigger: RunTrigger) -> Result<SyncRun> {
+        // A run that overruns the next scheduled time isn't started twice.
+        if !self.running.write().await.insert(job.id.clone()) {
+            return Err(ScheduleError::AlreadyRunning(job.name));
+        }
+
+        let mut run = SyncRun::new(
+            trigger,
+            &job.profile_id,
+            &job.bucket,
+            &job.prefix,
+            &job.local_path,
+        );
+        run.job_id = Some(job.id.clone());
+        run.job_name = Some(job.name.clone());
+        run.direction = Some(job.direction);
+        let _ = self.app_handle.emit("sync-job-started", &run);
+
+        let outcome: anyhow::Result<SyncResult> = async {
+            let client = s3_client::client_for_profile(&job.profile_id).await?;
+            client
+                .sync_folder(
+                    &job.bucket,
+                    &job.prefix,
+                    &job.local_path,
+                    job.direction,
+                    &job.options,
+                )
+                .await
+        }
+        .await;
+        run.finish(&outcome);
+
+        self.running.write().await.remove(&job.id);
+        self.run_log.record(run.clone()).await;
+        let _ = self.app_handle.emit("sync-job-finished", &run);
+
+        Ok(run)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_five_field_schedule_runs_on_the_minute() {
+        let schedule = parse_schedule("30 2 * * *").unwrap();
+        let since = Local.with_ymd_and_hms(2024, 1, 1, 2, 29, 50).unwrap();
+
+        assert!(is_due(
+            &schedule,
+            since,
+            since + chrono::Duration::seconds(10)
+        ));
+        assert!(!is_due(
+            &schedule,
+            since,
+            since + chrono::Duration::seconds(9)
+        ));
+    }
+
+    #[test]
+    fn test_weekday_range_counts_from_sunday_as_zero() {
+        let schedule = parse_schedule("0 2 * * 1-5").unwrap();
+        // 2024-01-01 was a Monday.
+        let monday = Local.with_ymd_and_hms(2024, 1, 1, 1, 0, 0).unwrap();
+        let sunday = Local.with_ymd_and_hms(2023, 12, 31, 1, 0, 0).unwrap();
+
+        assert!(is_due(
+            &schedule,
+            monday,
+            monday + chrono::Duration::hours(2)
+        ));
+        assert!(!is_due(
+            &schedule,
+            sunday,
+            sunday + chrono::Duration::hours(2)
+        ));
+    }
+
+    #[test]
+    fn test_sunday_is_zero_or_seven() {
+        assert_eq!(crontab_weekdays("0"), "1");
+        assert_eq!(crontab_weekdays("7"), "1");
+        assert_eq!(crontab_weekdays("6"), "7");
+        assert_eq!(crontab_weekdays("5-7"), "6-7,1");
+        assert_eq!(crontab_weekdays("0-6/2"), "1-7/2");
+        assert_eq!(crontab_weekdays("MON-FRI"), "MON-FRI");
+        assert!(parse_schedule("* * * * 0").is_ok());
+    }
+
+    #[test]
+    fn test_invalid_schedule_is_rejected() {
+        assert!(matches!(
+            parse_schedule("every night"),
+            Err(ScheduleError::InvalidSchedule { .. })
+        ));
+    }
+}
@@ -1,8 +1,9 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::fs::File;
 use std::io::Read;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
 use chrono::{DateTime, Utc};
 use md5::{Digest, Md5};
@@ -13,6 +14,22 @@ use thiserror::Error;
 use tokio::sync::{mpsc, RwLock};
 use uuid::Uuid;
 
+use crate::config::{self, KeepSyncJob};
+use crate::filter::{FilterRules, PathFilter, IGNORE_FILE_NAME};
+use crate::hash_cache::{self, FileStat};
+use crate::run_log::{RunLog, RunTrigger, SyncRun};
+use crate::s3_client::{
+    self, ObjectInfo, S3Client, SyncDirection, SyncFileError, SyncOptions, SyncResult,
+};
+use crate::sync_db::{SyncDatabase, SyncRecord};
+
+/// Quiet period used when a KeepSync is started without one.
+pub const DEFAULT_DEBOUNCE_MS: u64 = 1000;
+
+/// Editor swap and lock files that appear and vanish around every save.
+const TEMP_FILE_PREFIXES: &[&str] = &["~$", ".#"];
+const TEMP_FILE_SUFFIXES: &[&str] = &[".swp"];
+
 #[derive(Error, Debug)]
 pub enum SyncError {
     #[error("IO error: {0}")]
@@ -23,6 +40,12 @@ pub enum SyncError {
     NotFound(String),
     #[error("Sync already exists for this path")]
     AlreadyExists,
+    #[error("S3 error: {0}")]
+    S3(String),
+    #[error("Invalid filter: {0}")]
+    Filter(String),
+    #[error("Config error: {0}")]
+    Config(#[from] config::ConfigError),
 }
 
 pub type Result<T> = std::result::Result<T, SyncError>;
@@ -36,6 +59,202 @@ pub struct SyncState {
     pub local_path: String,
     pub is_active: bool,
     pub last_sync: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub files_uploaded: u64,
+    #[serde(default)]
+    pub files_deleted: u64,
+    #[serde(default)]
+    pub files_downloaded: u64,
+    /// How long the folder has to be quiet before pending changes are synced.
+    #[serde(default = "default_debounce_ms")]
+    pub debounce_ms: u64,
+    #[serde(default)]
+    pub filters: FilterRules,
+    /// Seconds between checks of the bucket for changes made elsewhere.
+    /// `None` only checks when asked to.
+    #[serde(default)]
+    pub poll_interval_secs: Option<u64>,
+    #[serde(default)]
+    pub remote_deletes: RemoteDeletePolicy,
+}
+
+/// What a KeepSync does with a local file whose object was deleted from
+/// the bucket by someone else.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RemoteDeletePolicy {
+    #[default]
+    KeepLocal,
+    /// Only files unchanged since they were last synced; edited or never
+    /// synced ones are uploaded again.
+    DeleteLocal,
+}
+
+fn default_debounce_ms() -> u64 {
+    DEFAULT_DEBOUNCE_MS
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncAction {
+    #[default]
+    Skip,
+    Upload,
+    Download,
+    DeleteLocal,
+    DeleteRemote,
+    /// Both sides changed since the last agreed state.
+    Conflict,
+}
+
+/// How a sync decides that a file present on both sides is up to date.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CompareMode {
+    /// Hash each local file and compare it with the object's ETag.
+    #[default]
+    Checksum,
+    /// Same size and modification time, using the `x-amz-meta-mtime`
+    /// recorded at upload when the object has one.
+    SizeMtime,
+    SizeOnly,
+    /// Never re-transfer a file that exists on both sides.
+    Existence,
+}
+
+/// What is known about when a remote object's content last changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemoteMtime {
+    /// The local modification time stored in the object's metadata.
+    Recorded(DateTime<Utc>),
+    /// Only when the object itself was written.
+    LastModified(DateTime<Utc>),
+    Unknown,
+}
+
+/// Recorded and local mtimes closer than this count as equal, so files on
+/// filesystems that only keep whole seconds don't look modified.
+const MTIME_TOLERANCE_MS: i64 = 1000;
+
+impl CompareMode {
+    /// Decides from sizes and times alone whether the local file matches
+    /// the remote object. `Checksum` needs the content, so only its size
+    /// check applies here.
+    pub fn metadata_matches(
+        self,
+        local_size: u64,
+        local_mtime: DateTime<Utc>,
+        remote_size: i64,
+        remote_mtime: RemoteMtime,
+    ) -> bool {
+        let same_size = i64::try_from(local_size).ok() == Some(remote_size);
+
+        match self {
+            CompareMode::Existence => true,
+            CompareMode::SizeOnly | CompareMode::Checksum => same_size,
+            CompareMode::SizeMtime => {
+                same_size
+                    && match remote_mtime {
+                        RemoteMtime::Recorded(recorded) => {
+                            (recorded - local_mtime).num_milliseconds().abs() < MTIME_TOLERANCE_MS
+                        }
+                        // Written after the file last changed means it holds that change.
+                        RemoteMtime::LastModified(written) => written >= local_mtime,
+                        RemoteMtime::Unknown => false,
+                    }
+            }
+        }
+    }
+
+    /// Why a file that doesn't match is transferred, for sync plans.
+    pub fn mismatch_reason(self) -> &'static str {
+        match self {
+            CompareMode::Checksum => "Content differs",
+            CompareMode::SizeMtime => "Size or modification time differs",
+            CompareMode::SizeOnly => "Size differs",
+            CompareMode::Existence => "Missing",
+        }
+    }
+}
+
+/// What to do when a path changed on both sides since the last sync.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictPolicy {
+    NewestWins,
+    LocalWins,
+    RemoteWins,
+    /// Keep the winner under the original name and the older copy under a
+    /// `.conflict-<timestamp>` name.
+    KeepBoth,
+    /// Leave both sides alone and report the conflict for the UI to resolve.
+    #[default]
+    AskUser,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictResolution {
+    /// Keep the local copy.
+    Local,
+    /// Keep the remote copy.
+    Remote,
+    /// Keep both, renaming the older one.
+    Both,
+}
+
+/// A path that changed on both sides. A missing size/modified time means
+/// that side deleted the file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncConflict {
+    pub path: String,
+    pub local_size: Option<u64>,
+    pub local_modified: Option<DateTime<Utc>>,
+    pub remote_size: Option<i64>,
+    pub remote_modified: Option<DateTime<Utc>>,
+    /// How the conflict was settled, or `None` while it waits for the user.
+    pub resolution: Option<ConflictResolution>,
+}
+
+impl ConflictPolicy {
+    pub fn resolve(self, conflict: &SyncConflict) -> Option<ConflictResolution> {
+        match self {
+            ConflictPolicy::LocalWins => Some(ConflictResolution::Local),
+            ConflictPolicy::RemoteWins => Some(ConflictResolution::Remote),
+            ConflictPolicy::KeepBoth => Some(ConflictResolution::Both),
+            ConflictPolicy::AskUser => None,
+            // A deletion has no timestamp, so an edit always beats it.
+            ConflictPolicy::NewestWins => match (conflict.local_modified, conflict.remote_modified)
+            {
+                (Some(local), Some(remote)) if local >= remote => Some(ConflictResolution::Local),
+                (Some(_), Some(_)) => Some(ConflictResolution::Remote),
+                (Some(_), None) => Some(ConflictResolution::Local),
+                (None, Some(_)) => Some(ConflictResolution::Remote),
+                (None, None) => None,
+            },
+        }
+    }
+}
+
+/// Name for the losing copy in a keep-both resolution, e.g.
+/// `notes.txt` becomes `notes.conflict-20240101-120000.txt`.
+pub fn conflict_copy_name(path: &str, at: DateTime<Utc>) -> String {
+    let stamp = at.format("%Y%m%d-%H%M%S");
+    let (dir, file_name) = match path.rfind('/') {
+        Some(index) => path.split_at(index + 1),
+        None => ("", path),
+    };
+
+    match file_name.rfind('.') {
+        Some(dot) if dot > 0 => format!(
+            "{}{}.conflict-{}{}",
+            dir,
+            &file_name[..dot],
+            stamp,
+            &file_name[dot..]
+        ),
+        _ => format!("{}{}.conflict-{}", dir, file_name, stamp),
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -45,6 +264,15 @@ pub struct SyncEntry {
     pub remote_etag: Option<String>,
     pub needs_upload: bool,
     pub needs_download: bool,
+    /// State recorded at the end of the previous bidirectional sync.
+    #[serde(default)]
+    pub base: Option<SyncRecord>,
+    #[serde(default)]
+    pub action: SyncAction,
+    /// Result of a deeper content comparison, for ETags that aren't a plain
+    /// MD5 of the file.
+    #[serde(default)]
+    pub same_content: Option<bool>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -68,6 +296,7 @@ pub struct SyncCompletedPayload {
     pub sync_id: String,
     pub files_uploaded: u64,
     pub files_downloaded: u64,
+    pub files_deleted: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -79,45 +308,128 @@ pub struct SyncErrorPayload {
 struct WatcherHandle {
     _watcher: RecommendedWatcher,
     shutdown_tx: mpsc::Sender<()>,
+    poll_tx: mpsc::Sender<()>,
+}
+
+/// What a filesystem event means for the remote side.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum FileChange {
+    /// The path was created or modified and should be uploaded.
+    Changed(PathBuf),
+    /// The path is gone and its object(s) should be deleted.
+    Removed(PathBuf),
 }
 
+#[derive(Clone)]
 pub struct SyncManager {
     active_syncs: Arc<RwLock<HashMap<String, SyncState>>>,
     watcher_handles: Arc<RwLock<HashMap<String, WatcherHandle>>>,
+    run_log: RunLog,
     app_handle: AppHandle,
 }
 
 impl SyncManager {
-    pub fn new(app_handle: AppHandle) -> Self {
+    pub fn new(app_handle: AppHandle, run_log: RunLog) -> Self {
         Self {
             active_syncs: Arc::new(RwLock::new(HashMap::new())),
             watcher_handles: Arc::new(RwLock::new(HashMap::new())),
+            run_log,
             app_handle,
         }
     }
 
+    /// Starts watching `local_path` and saves the job so it is started
+    /// again the next time the app runs.
+    #[allow(clippy::too_many_arguments)]
     pub async fn start_keep_sync(
         &self,
         profile_id: &str,
         bucket: &str,
         prefix: &str,
         local_path: &str,
+        debounce_ms: u64,
+        filters: FilterRules,
+        poll_interval_secs: Option<u64>,
+        remote_deletes: RemoteDeletePolicy,
     ) -> Result<String> {
-        let path = Path::new(local_path);
+        let job = KeepSyncJob {
+            id: Uuid::new_v4().to_string(),
+            profile_id: profile_id.to_string(),
+            bucket: bucket.to_string(),
+            prefix: prefix.to_string(),
+            local_path: local_path.to_string(),
+            debounce_ms,
+            filters,
+            poll_interval_secs,
+            remote_deletes,
+        };
+
+        let sync_id = self.run_keep_sync(job.clone(), false).await?;
+
+        let saved = config::load_config().and_then(|mut config| {
+            config.keep_syncs.push(job);
+            config::save_config(&config)
+        });
+        if let Err(e) = saved {
+            let _ = self.stop_keep_sync(&sync_id).await;
+            return Err(e.into());
+        }
+
+        Ok(sync_id)
+    }
+
+    /// Starts every KeepSync saved in the config. Each first catches up on
+    /// changes made while the app was closed.
+    pub async fn restore_keep_syncs(&self) {
+        let jobs = match config::load_config() {
+            Ok(config) => config.keep_syncs,
+            Err(_) => return,
+        };
+
+        for job in jobs {
+            let sync_id = job.id.clone();
+            if let Err(e) = self.run_keep_sync(job, true).await {
+                self.emit_sync_error(&sync_id, &e.to_string());
+            }
+        }
+    }
+
+    async fn run_keep_sync(&self, job: KeepSyncJob, catch_up: bool) -> Result<String> {
+        let path = Path::new(&job.local_path);
         if !path.exists() {
+            // A missing folder on restore is more likely an unplugged drive
+            // than an empty one; catching up would delete everything.
+            if catch_up {
+                return Err(SyncError::Io(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("Local folder not found: {}", job.local_path),
+                )));
+            }
             std::fs::create_dir_all(path)?;
         }
 
-        let sync_id = Uuid::new_v4().to_string();
+        let client = s3_client::client_for_profile(&job.profile_id)
+            .await
+            .map_err(|e| SyncError::S3(format!("{:#}", e)))?;
+
+        let sync_id = job.id;
+        let local_path = job.local_path.as_str();
 
         let state = SyncState {
             sync_id: sync_id.clone(),
-            profile_id: profile_id.to_string(),
-            bucket: bucket.to_string(),
-            remote_prefix: prefix.to_string(),
-            local_path: local_path.to_string(),
+            profile_id: job.profile_id.clone(),
+            bucket: job.bucket.clone(),
+            remote_prefix: job.prefix.clone(),
+            local_path: job.local_path.clone(),
             is_active: true,
             last_sync: None,
+            files_uploaded: 0,
+            files_deleted: 0,
+            files_downloaded: 0,
+            debounce_ms: job.debounce_ms,
+            filters: job.filters,
+            poll_interval_secs: job.poll_interval_secs.filter(|secs| *secs > 0),
+            remote_deletes: job.remote_deletes,
         };
 
         {
@@ -130,6 +442,7 @@ impl SyncManager {
         }
 
         let (shutdown_tx, mut shutdown_rx) = mpsc::channel::<()>(1);
+        let (poll_tx, mut poll_rx) = mpsc::channel::<()>(1);
         let (event_tx, mut event_rx) = mpsc::channel::<notify::Result<Event>>(100);
 
         let mut watcher = RecommendedWatcher::new(
@@ -141,11 +454,70 @@ impl SyncManager {
 
         watcher.watch(path, RecursiveMode::Recursive)?;
 
-        let app_handle = self.app_handle.clone();
-        let sync_id_clone = sync_id.clone();
-        let local_path_clone = local_path.to_string();
+        let mut filter = PathFilter::new(path, &state.filters)
+            .map_err(|e| SyncError::Filter(format!("{:#}", e)))?;
+        let ignore_file = path.join(IGNORE_FILE_NAME);
+
+        self.active_syncs
+            .write()
+            .await
+            .insert(sync_id.clone(), state.clone());
+        self.watcher_handles.write().await.insert(
+            sync_id.clone(),
+            WatcherHandle {
+                _watcher: watcher,
+                shutdown_tx,
+                poll_tx,
+            },
+        );
+
+        let manager = self.clone();
+        let sync = state;
 
         tokio::spawn(async move {
+            // The watcher is already running, so nothing changed during the
+            // catch-up is missed; those events are handled right after.
+            if catch_up {
+                manager.catch_up(&client, &sync).await;
+            }
+
+            // Kept current as files are synced, so the next catch-up knows
+            // what both sides last agreed on.
+            let mut db = match SyncDatabase::load(
+                &sync.profile_id,
+                &sync.bucket,
+                &sync.remote_prefix,
+                &sync.local_path,
+            ) {
+                Ok(db) => Some(db),
+                Err(e) => {
+                    manager.emit_sync_error(&sync.sync_id, &format!("{:#}", e));
+                    None
+                }
+            };
+
+            // What the bucket held at the last poll; changes are found by
+            // comparing a new listing against it.
+            let mut known_remote = match client
+                .remote_files(&sync.bucket, &sync.remote_prefix, &filter)
+                .await
+            {
+                Ok(listing) => Some(remote_snapshot(&listing)),
+                Err(e) => {
+                    manager.emit_sync_error(&sync.sync_id, &format!("{:#}", e));
+                    None
+                }
+            };
+            let poll_every = sync.poll_interval_secs.map(Duration::from_secs);
+            let mut poll_at = poll_every.map(|every| tokio::time::Instant::now() + every);
+
+            // Paths touched since the last flush. Each is resolved against
+            // the disk once the folder has been quiet for `debounce_ms`, so a
+            // burst of events for one save turns into a single upload.
+            let mut pending: BTreeSet<PathBuf> = BTreeSet::new();
+            let mut flush_at: Option<tokio::time::Instant> = None;
+            let debounce = Duration::from_millis(sync.debounce_ms);
+
             loop {
                 tokio::select! {
                     _ = shutdown_rx.recv() => {
@@ -153,46 +525,66 @@ impl SyncManager {
                     }
                     Some(event_result) = event_rx.recv() => {
                         if let Ok(event) = event_result {
-                            Self::handle_file_event(&app_handle, &sync_id_clone, &local_path_clone, event).await;
+                            let paths = changed_paths(&event);
+                            if paths.contains(&ignore_file) {
+                                match PathFilter::new(filter.root(), &sync.filters) {
+                                    Ok(updated) => filter = updated,
+                                    Err(e) => manager.emit_sync_error(&sync.sync_id, &format!("{:#}", e)),
+                                }
+                            }
+                            pending.extend(paths.into_iter().filter(|p| is_watched(&filter, p)));
+                            if !pending.is_empty() {
+                                flush_at = Some(tokio::time::Instant::now() + debounce);
+                            }
                         }
                     }
+                    _ = tokio::time::sleep_until(flush_at.unwrap_or_else(tokio::time::Instant::now)), if flush_at.is_some() => {
+                        flush_at = None;
+                        let changes = coalesce_changes(std::mem::take(&mut pending));
+                        manager.handle_changes(&client, &sync, &filter, db.as_mut(), changes).await;
+                    }
+                    Some(()) = poll_rx.recv() => {
+                        manager.poll_remote(&client, &sync, &filter, &pending, &mut known_remote, db.as_mut()).await;
+                    }
+                    _ = tokio::time::sleep_until(poll_at.unwrap_or_else(tokio::time::Instant::now)), if poll_at.is_some() => {
+                        manager.poll_remote(&client, &sync, &filter, &pending, &mut known_remote, db.as_mut()).await;
+                        poll_at = poll_every.map(|every| tokio::time::Instant::now() + every);
+                    }
                 }
             }
         });
 
-        let watcher_handle = WatcherHandle {
-            _watcher: watcher,
-            shutdown_tx,
-        };
-
-        self.active_syncs
-            .write()
-            .await
-            .insert(sync_id.clone(), state);
-        self.watcher_handles
-            .write()
-            .await
-            .insert(sync_id.clone(), watcher_handle);
-
         let _ = self.app_handle.emit(
             "sync-started",
             SyncStartedPayload {
                 sync_id: sync_id.clone(),
-                local_path: local_path.to_string(),
-                bucket: bucket.to_string(),
-                prefix: prefix.to_string(),
+                local_path: job.local_path,
+                bucket: job.bucket,
+                prefix: job.prefix,
             },
         );
 
         Ok(sync_id)
     }
 
+    /// Stops a KeepSync and removes it from the config. A saved job that
+    /// couldn't be restored can be removed this way too.
     pub async fn stop_keep_sync(&self, sync_id: &str) -> Result<()> {
+        let mut config = config::load_config()?;
+        let saved = config.keep_syncs.len();
+        config.keep_syncs.retain(|job| job.id != sync_id);
+        let forgotten = config.keep_syncs.len() != saved;
+        if forgotten {
+            config::save_config(&config)?;
+        }
+
         let mut syncs = self.active_syncs.write().await;
         let mut handles = self.watcher_handles.write().await;
 
         if let Some(state) = syncs.get_mut(sync_id) {
             state.is_active = false;
+        } else if forgotten {
+            return Ok(());
         } else {
             return Err(SyncError::NotFound(sync_id.to_string()));
         }
@@ -201,17 +593,26 @@ impl SyncManager {
             let _ = handle.shutdown_tx.send(()).await;
         }
 
-        syncs.remove(sync_id);
+        if let Some(state) = syncs.remove(sync_id) {
+            self.emit_sync_completed(
+                sync_id,
+                state.files_uploaded,
+                state.files_downloaded,
+                state.files_deleted,
+            );
+        }
 
-        let _ = self.app_handle.emit(
-            "sync-completed",
-            SyncCompletedPayload {
-                sync_id: sync_id.to_string(),
-                files_uploaded: 0,
-                files_downloaded: 0,
-            },
-        );
+        Ok(())
+    }
 
+    /// Checks the bucket of a running KeepSync for changes right away.
+    pub async fn poll_keep_sync(&self, sync_id: &str) -> Result<()> {
+        let handles = self.watcher_handles.read().await;
+        let handle = handles
+            .get(sync_id)
+            .ok_or_else(|| SyncError::NotFound(sync_id.to_string()))?;
+        // A full channel means a poll is already on its way.
+        let _ = handle.poll_tx.try_send(());
         Ok(())
     }
 
@@ -225,30 +626,471 @@ impl SyncManager {
             .collect()
     }
 
-    async fn handle_file_event(
-        app_handle: &AppHandle,
-        sync_id: &str,
-        _local_path: &str,
-        event: Event,
+    /// Brings both sides up to date after the app was closed, the same way
+    /// a bidirectional sync would. The sync database tells a file deleted
+    /// here apart from one added to the bucket, so nothing is deleted
+    /// remotely without a record of it having been synced, and remote edits
+    /// are pulled rather than overwritten. Conflicts are left for the user.
+    async fn catch_up(&self, client: &S3Client, sync: &SyncState) {
+        let options = SyncOptions {
+            filters: sync.filters.clone(),
+            conflict_policy: ConflictPolicy::AskUser,
+            ..SyncOptions::default()
+        };
+        let started_at = Utc::now();
+        let outcome = async {
+            let mut plan = client
+                .plan_sync(
+                    &sync.bucket,
+                    &sync.remote_prefix,
+                    &sync.local_path,
+                    SyncDirection::Bidirectional,
+                    &options,
+                )
+                .await?;
+            if sync.remote_deletes == RemoteDeletePolicy::KeepLocal {
+                // A kept file goes back up; left alone, its record would
+                // plan the same delete on every start.
+                for planned in &mut plan.actions {
+                    if planned.action == SyncAction::DeleteLocal {
+                        planned.action = SyncAction::Upload;
+                        planned.reason = "Deleted on the remote, kept locally".to_string();
+                    }
+                }
+            }
+            client.execute_sync_plan(&plan).await
+        }
+        .await;
+        self.record_run(sync, started_at, &outcome).await;
+
+        let result = match outcome {
+            Ok(result) => result,
+            Err(e) => {
+                self.emit_sync_error(&sync.sync_id, &format!("Catch-up failed: {:#}", e));
+                return;
+            }
+        };
+
+        for error in &result.errors {
+            self.emit_sync_error(&sync.sync_id, &format!("{}: {}", error.path, error.message));
+        }
+        for conflict in &result.conflicts {
+            self.emit_sync_error(
+                &sync.sync_id,
+                &format!("{}: changed on both sides, left as is", conflict.path),
+            );
+        }
+        if let Some(state) = self.active_syncs.write().await.get_mut(&sync.sync_id) {
+            state.last_sync = Some(Utc::now());
+            state.files_uploaded += result.uploaded;
+            state.files_downloaded += result.downloaded;
+            state.files_deleted += result.deleted;
+        }
+        self.emit_sync_completed(
+            &sync.sync_id,
+            result.uploaded,
+            result.downloaded,
+            result.deleted,
+        );
+    }
+
+    /// Downloads objects that changed in the bucket since the last poll and
+    /// applies the job's policy to ones deleted there. Paths with local
+    /// changes still waiting to be uploaded are left for the next poll.
+    async fn poll_remote(
+        &self,
+        client: &S3Client,
+        sync: &SyncState,
+        filter: &PathFilter,
+        pending: &BTreeSet<PathBuf>,
+        known_remote: &mut Option<HashMap<String, String>>,
+        mut db: Option<&mut SyncDatabase>,
     ) {
-        use notify::EventKind;
+        let listing = match client
+            .remote_files(&sync.bucket, &sync.remote_prefix, filter)
+            .await
+        {
+            Ok(listing) => listing,
+            Err(e) => {
+                self.emit_sync_error(&sync.sync_id, &format!("{:#}", e));
+                return;
+            }
+        };
+
+        let mut current = remote_snapshot(&listing);
+        let Some(previous) = known_remote.take() else {
+            *known_remote = Some(current);
+            return;
+        };
+
+        let root = Path::new(&sync.local_path);
+        let started_at = Utc::now();
+        let mut result = SyncResult::default();
+
+        for (path, obj) in &listing {
+            if previous.get(path) == current.get(path) {
+                continue;
+            }
+            let local = root.join(path);
+            if pending.contains(&local) {
+                revert_snapshot(&mut current, &previous, path);
+                continue;
+            }
+
+            let db = db.as_deref_mut();
+            match Self::pull_remote_change(client, sync, path, &local, obj, db).await {
+                Ok(true) => result.record(path, SyncAction::Download, obj.size.max(0) as u64),
+                Ok(false) => {}
+                Err(e) => {
+                    self.emit_sync_error(&sync.sync_id, &format!("{}: {:#}", path, e));
+                    result.errors.push(SyncFileError::new(path, &e));
+                }
+            }
+        }
+
+        if sync.remote_deletes == RemoteDeletePolicy::DeleteLocal {
+            for path in previous.keys().filter(|path| !listing.contains_key(*path)) {
+                let local = root.join(path);
+                if pending.contains(&local) {
+                    revert_snapshot(&mut current, &previous, path);
+                    continue;
+                }
+
+                let db = db.as_deref_mut();
+                match Self::apply_remote_delete(client, sync, path, &local, db).await {
+                    Ok(Some((action, size))) => result.record(path, action, size),
+                    Ok(None) => {}
+                    Err(e) => {
+                        self.emit_sync_error(&sync.sync_id, &format!("{}: {:#}", path, e));
+                        result.errors.push(SyncFileError::new(path, &e));
+                    }
+                }
+            }
+        }
+
+        *known_remote = Some(current);
+        if !result.files.is_empty() {
+            self.save_db(sync, db.as_deref());
+        }
+
+        if !result.files.is_empty() {
+            if let Some(state) = self.active_syncs.write().await.get_mut(&sync.sync_id) {
+                state.last_sync = Some(Utc::now());
+                state.files_uploaded += result.uploaded;
+                state.files_downloaded += result.downloaded;
+                state.files_deleted += result.deleted;
+            }
+            self.emit_sync_completed(
+                &sync.sync_id,
+                result.uploaded,
+                result.downloaded,
+                result.deleted,
+            );
+        }
+        self.record_run(sync, started_at, &Ok(result)).await;
+    }
+
+    /// Downloads an object that changed remotely unless the local file
+    /// already matches it or was modified after it. Returns whether a
+    /// download happened.
+    async fn pull_remote_change(
+        client: &S3Client,
+        sync: &SyncState,
+        path: &str,
+        local: &Path,
+        obj: &ObjectInfo,
+        db: Option<&mut SyncDatabase>,
+    ) -> anyhow::Result<bool> {
+        if local.is_file() {
+            // Our own uploads show up as remote changes too.
+            if client
+                .object_matches_file(&sync.bucket, &obj.key, local)
+                .await?
+            {
+                return Ok(false);
+            }
+            // A newer local edit wins; the watcher uploads it.
+            let local_modified = DateTime::<Utc>::from(std::fs::metadata(local)?.modified()?);
+            if obj
+                .last_modified
+                .is_some_and(|remote| remote < local_modified)
+            {
+                return Ok(false);
+            }
+        }
+
+        client
+            .download_object_within(
+                &sync.bucket,
+                &obj.key,
+                &local.to_string_lossy(),
+                Path::new(&sync.local_path),
+            )
+            .await?;
+        if let Some(db) = db {
+            let record = client.record_for(local, None, obj.etag.clone()).await?;
+            db.records.insert(path.to_string(), record);
+        }
+        Ok(true)
+    }
+
+    /// Deletes the local copy of an object deleted from the bucket, but
+    /// only when the sync database shows it was synced and it hasn't been
+    /// touched since. Anything else is uploaded again. Returns what was
+    /// done and the size involved, or `None` if the file is already gone.
+    async fn apply_remote_delete(
+        client: &S3Client,
+        sync: &SyncState,
+        path: &str,
+        local: &Path,
+        db: Option<&mut SyncDatabase>,
+    ) -> anyhow::Result<Option<(SyncAction, u64)>> {
+        // The path comes from a key, which anyone with write access names.
+        s3_client::ensure_inside(Path::new(&sync.local_path), local).await?;
+        if !local.is_file() {
+            return Ok(None);
+        }
+
+        let unchanged = match db.as_deref().and_then(|db| db.records.get(path)) {
+            Some(record) => client
+                .local_md5_since(local, Some(record))
+                .await?
+                .eq_ignore_ascii_case(&record.local_md5),
+            None => false,
+        };
+        if unchanged {
+            std::fs::remove_file(local)?;
+            if let Some(db) = db {
+                db.records.remove(path);
+            }
+            return Ok(Some((SyncAction::DeleteLocal, 0)));
+        }
+
+        // Never synced, or edited since: keep it and put it back.
+        let size = std::fs::metadata(local)?.len();
+        let key = remote_key(sync, local);
+        let etag = client
+            .upload_file(&sync.bucket, &key, &local.to_string_lossy())
+            .await?;
+        if let Some(db) = db {
+            let record = client.record_for(local, None, etag).await?;
+            db.records.insert(path.to_string(), record);
+        }
+        Ok(Some((SyncAction::Upload, size)))
+    }
+
+    async fn handle_changes(
+        &self,
+        client: &S3Client,
+        sync: &SyncState,
+        filter: &PathFilter,
+        mut db: Option<&mut SyncDatabase>,
+        changes: Vec<FileChange>,
+    ) {
+        if changes.is_empty() {
+            return;
+        }
+
+        let total = changes.len() as u64;
+        let started_at = Utc::now();
+        let mut result = SyncResult::default();
+
+        for (index, change) in changes.iter().enumerate() {
+            let path = match change {
+                FileChange::Changed(path) | FileChange::Removed(path) => path,
+            };
+            self.emit_sync_progress(
+                &sync.sync_id,
+                index as u64 + 1,
+                total,
+                &path.display().to_string(),
+            );
+
+            let outcome = match change {
+                FileChange::Changed(path) => {
+                    let db = db.as_deref_mut();
+                    Self::upload_changed(client, sync, filter, path, db, &mut result).await
+                }
+                FileChange::Removed(path) => {
+                    let db = db.as_deref_mut();
+                    Self::delete_removed(client, sync, filter, path, db, &mut result).await
+                }
+            };
+
+            if let Err(e) = outcome {
+                self.emit_sync_error(&sync.sync_id, &format!("{}: {:#}", path.display(), e));
+                let relative = filter
+                    .relative_path(path)
+                    .unwrap_or_else(|| path.display().to_string());
+                result.errors.push(SyncFileError::new(&relative, &e));
+            }
+        }
+
+        let _ = hash_cache::save_shared().await;
+        if !result.files.is_empty() {
+            self.save_db(sync, db.as_deref());
+        }
+
+        if let Some(state) = self.active_syncs.write().await.get_mut(&sync.sync_id) {
+            state.last_sync = Some(Utc::now());
+            state.files_uploaded += result.uploaded;
+            state.files_deleted += result.deleted;
+        }
 
-        match event.kind {
-            EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_) => {
-                for path in &event.paths {
-                    let _ = app_handle.emit(
-                        "sync-progress",
-                        SyncProgressPayload {
-                            sync_id: sync_id.to_string(),
-                            current: 0,
-                            total: 1,
-                            current_file: path.display().to_string(),
-                        },
-                    );
+        self.emit_sync_completed(&sync.sync_id, result.uploaded, 0, result.deleted);
+        self.record_run(sync, started_at, &Ok(result)).await;
+    }
+
+    fn save_db(&self, sync: &SyncState, db: Option<&SyncDatabase>) {
+        if let Some(Err(e)) = db.map(SyncDatabase::save) {
+            self.emit_sync_error(&sync.sync_id, &format!("{:#}", e));
+        }
+    }
+
+    /// Adds a KeepSync pass to the run log, unless it had nothing to do.
+    async fn record_run(
+        &self,
+        sync: &SyncState,
+        started_at: DateTime<Utc>,
+        outcome: &anyhow::Result<SyncResult>,
+    ) {
+        if let Ok(result) = outcome {
+            if result.files.is_empty() && result.errors.is_empty() {
+                return;
+            }
+        }
+
+        let mut run = SyncRun::new(
+            RunTrigger::KeepSync,
+            &sync.profile_id,
+            &sync.bucket,
+            &sync.remote_prefix,
+            &sync.local_path,
+        );
+        run.job_id = Some(sync.sync_id.clone());
+        run.started_at = started_at;
+        run.finish(outcome);
+        self.run_log.record(run).await;
+    }
+
+    /// Uploads a changed file, or every file under a changed directory,
+    /// adding each upload to `result`.
+    async fn upload_changed(
+        client: &S3Client,
+        sync: &SyncState,
+        filter: &PathFilter,
+        path: &Path,
+        mut db: Option<&mut SyncDatabase>,
+        result: &mut SyncResult,
+    ) -> anyhow::Result<()> {
+        if path.is_file() {
+            let Some(relative) = filter
+                .relative_path(path)
+                .filter(|relative| filter.is_included(relative))
+            else {
+                return Ok(());
+            };
+
+            // Saving without changes, or touching a file, still fires events.
+            let key = remote_key(sync, path);
+            if client.object_matches_file(&sync.bucket, &key, path).await? {
+                return Ok(());
+            }
+            let size = std::fs::metadata(path)?.len();
+            let etag = client
+                .upload_file(&sync.bucket, &key, &path.to_string_lossy())
+                .await?;
+            if let Some(db) = db {
+                let record = client.record_for(path, None, etag).await?;
+                db.records.insert(relative.clone(), record);
+            }
+            result.record(&relative, SyncAction::Upload, size);
+            return Ok(());
+        }
+
+        if path.is_dir() {
+            // A directory moved into the watched tree only produces one event.
+            for (relative, file) in filter.files_under(path) {
+                if !is_ignored_file(&file) {
+                    let key = remote_key(sync, &file);
+                    let size = std::fs::metadata(&file)?.len();
+                    let etag = client
+                        .upload_file(&sync.bucket, &key, &file.to_string_lossy())
+                        .await?;
+                    if let Some(db) = db.as_deref_mut() {
+                        let record = client.record_for(&file, None, etag).await?;
+                        db.records.insert(relative.clone(), record);
+                    }
+                    result.record(&relative, SyncAction::Upload, size);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Deletes the object for a removed path. When there is no object at
+    /// that key the path was a directory, so anything under it as a prefix
+    /// goes instead. Each delete is added to `result`.
+    async fn delete_removed(
+        client: &S3Client,
+        sync: &SyncState,
+        filter: &PathFilter,
+        path: &Path,
+        mut db: Option<&mut SyncDatabase>,
+        result: &mut SyncResult,
+    ) -> anyhow::Result<()> {
+        if path.exists() {
+            return Ok(());
+        }
+
+        let key = remote_key(sync, path);
+        let relative = filter.relative_path(path).unwrap_or_default();
+        if let Some(object) = client.head_object_info(&sync.bucket, &key).await? {
+            client.delete_object(&sync.bucket, &key).await?;
+            if let Some(db) = db {
+                db.records.remove(&relative);
+            }
+            result.record(
+                &relative,
+                SyncAction::DeleteRemote,
+                object.size.max(0) as u64,
+            );
+            return Ok(());
+        }
+
+        // Keys the filter hides were never ours to manage.
+        let nested: Vec<(String, ObjectInfo)> = client
+            .list_objects_recursive(&sync.bucket, &format!("{}/", key))
+            .await?
+            .into_iter()
+            .map(|o| (format!("{}/{}", relative, &o.key[key.len() + 1..]), o))
+            .filter(|(nested_path, _)| filter.is_included(nested_path))
+            .collect();
+        let keys: Vec<String> = nested.iter().map(|(_, o)| o.key.clone()).collect();
+        let deleted = client.delete_objects(&sync.bucket, &keys).await?;
+
+        for (nested_path, obj) in &nested {
+            match deleted.errors.iter().find(|e| e.key == obj.key) {
+                Some(error) => result.errors.push(SyncFileError {
+                    path: nested_path.clone(),
+                    message: error.message.clone(),
+                    code: error.code.clone(),
+                }),
+                None => {
+                    if let Some(db) = db.as_deref_mut() {
+                        db.records.remove(nested_path);
+                    }
+                    result.record(
+                        nested_path,
+                        SyncAction::DeleteRemote,
+                        obj.size.max(0) as u64,
+                    )
                 }
             }
-            _ => {}
         }
+
+        Ok(())
     }
 
     pub fn emit_sync_error(&self, sync_id: &str, error: &str) {
@@ -278,6 +1120,7 @@ impl SyncManager {
         sync_id: &str,
         files_uploaded: u64,
         files_downloaded: u64,
+        files_deleted: u64,
     ) {
         let _ = self.app_handle.emit(
             "sync-completed",
@@ -285,12 +1128,115 @@ impl SyncManager {
                 sync_id: sync_id.to_string(),
                 files_uploaded,
                 files_downloaded,
+                files_deleted,
             },
         );
     }
 }
 
+/// Whether an event for `path` concerns the sync at all.
+fn is_watched(filter: &PathFilter, path: &Path) -> bool {
+    if is_ignored_file(path) {
+        return false;
+    }
+
+    match filter.relative_path(path) {
+        Some(relative) if !relative.is_empty() => !filter.is_excluded(&relative, path.is_dir()),
+        _ => false,
+    }
+}
+
+/// ETag of every object in a listing, keyed by path.
+fn remote_snapshot(listing: &HashMap<String, ObjectInfo>) -> HashMap<String, String> {
+    listing
+        .iter()
+        .map(|(path, obj)| (path.clone(), obj.etag.clone().unwrap_or_default()))
+        .collect()
+}
+
+/// Puts back what the previous snapshot knew about `path`, so a change
+/// that was skipped is seen again on the next poll.
+fn revert_snapshot(
+    current: &mut HashMap<String, String>,
+    previous: &HashMap<String, String>,
+    path: &str,
+) {
+    match previous.get(path) {
+        Some(etag) => current.insert(path.to_string(), etag.clone()),
+        None => current.remove(path),
+    };
+}
+
+/// Maps a path inside the watched folder to its object key.
+fn remote_key(sync: &SyncState, path: &Path) -> String {
+    let relative = path
+        .strip_prefix(&sync.local_path)
+        .unwrap_or(path)
+        .to_string_lossy();
+    s3_client::join_key(&sync.remote_prefix, &relative)
+}
+
+/// Paths a filesystem event touched, skipping events that can't change
+/// what should be in the bucket.
+fn changed_paths(event: &Event) -> Vec<PathBuf> {
+    use notify::event::ModifyKind;
+    use notify::EventKind;
+
+    match event.kind {
+        EventKind::Create(_) | EventKind::Remove(_) => event.paths.clone(),
+        EventKind::Modify(ModifyKind::Name(_)) => event.paths.clone(),
+        // Permission and timestamp changes don't touch the contents.
+        EventKind::Modify(ModifyKind::Metadata(_)) => Vec::new(),
+        // A directory being "modified" just means its entries changed, and
+        // those entries get events of their own.
+        EventKind::Modify(_) => event.paths.iter().filter(|p| !p.is_dir()).cloned().collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Turns a batch of touched paths into one change per path based on what
+/// is on disk now. Paths under another path in the batch are dropped, since
+/// uploading or deleting the parent already covers them.
+fn coalesce_changes(paths: BTreeSet<PathBuf>) -> Vec<FileChange> {
+    let mut kept: Vec<PathBuf> = Vec::new();
+    for path in paths {
+        if kept.iter().any(|parent| path.starts_with(parent)) {
+            continue;
+        }
+        kept.push(path);
+    }
+
+    kept.into_iter()
+        .map(|path| {
+            if path.exists() {
+                FileChange::Changed(path)
+            } else {
+                FileChange::Removed(path)
+            }
+        })
+        .collect()
+}
+
+/// Editor temp files and our own partial downloads never get synced.
+pub fn is_ignored_file(path: &Path) -> bool {
+    if s3_client::is_partial_download(path) {
+        return true;
+    }
+
+    let Some(name) = path.file_name().map(|n| n.to_string_lossy()) else {
+        return false;
+    };
+    TEMP_FILE_PREFIXES.iter().any(|p| name.starts_with(p))
+        || TEMP_FILE_SUFFIXES.iter().any(|s| name.ends_with(s))
+}
+
+/// MD5 of a local file, from the hash cache if its stat hasn't changed.
 pub fn calculate_file_md5(path: &Path) -> Result<String> {
+    let stat = FileStat::from_metadata(&std::fs::metadata(path)?);
+    if let Some(md5) = hash_cache::with_shared(|cache| cache.md5(path, &stat)) {
+        return Ok(md5);
+    }
+
     let mut file = File::open(path)?;
     let mut hasher = Md5::new();
     let mut buffer = [0u8; 8192];
@@ -303,8 +1249,11 @@ pub fn calculate_file_md5(path: &Path) -> Result<String> {
         hasher.update(&buffer[..bytes_read]);
     }
 
-    let result = hasher.finalize();
-    Ok(format!("{:x}", result))
+    let md5 = format!("{:x}", hasher.finalize());
+    if FileStat::from_metadata(&std::fs::metadata(path)?) == stat {
+        hash_cache::with_shared(|cache| cache.insert_md5(path, &stat, md5.clone()));
+    }
+    Ok(md5)
 }
 
 pub fn compare_checksums(local_md5: &str, etag: &str) -> bool {
@@ -317,6 +1266,33 @@ pub fn compare_checksums(local_md5: &str, etag: &str) -> bool {
     local_md5.eq_ignore_ascii_case(etag_clean)
 }
 
+/// Number of parts encoded in a multipart upload's ETag (`"<md5>-<parts>"`).
+pub fn multipart_part_count(etag: &str) -> Option<u64> {
+    let (_, parts) = etag.trim_matches('"').rsplit_once('-')?;
+    parts.parse().ok().filter(|&n| n > 0)
+}
+
+/// Part sizes worth trying when reproducing a multipart ETag with `parts`
+/// parts for a file of `size` bytes: the `preferred` sizes first, then the
+/// defaults of common tools, then an even split rounded up to a MiB.
+pub fn candidate_part_sizes(size: u64, parts: u64, preferred: &[u64]) -> Vec<u64> {
+    const MIB: u64 = 1024 * 1024;
+    let common = [5, 8, 15, 16, 32, 50, 64, 100, 128, 256, 512].map(|mb| mb * MIB);
+    let per_part = (size + parts - 1) / parts;
+    let even_split = (per_part + MIB - 1) / MIB * MIB;
+
+    let mut candidates = Vec::new();
+    for part_size in preferred.iter().copied().chain(common).chain([even_split]) {
+        if part_size > 0
+            && (size + part_size - 1) / part_size == parts
+            && !candidates.contains(&part_size)
+        {
+            candidates.push(part_size);
+        }
+    }
+    candidates
+}
+
 impl SyncEntry {
     pub fn new(path: String) -> Self {
         Self {
@@ -325,6 +1301,9 @@ impl SyncEntry {
             remote_etag: None,
             needs_upload: false,
             needs_download: false,
+            base: None,
+            action: SyncAction::Skip,
+            same_content: None,
         }
     }
 
@@ -338,10 +1317,25 @@ impl SyncEntry {
         self
     }
 
+    pub fn with_base(mut self, base: SyncRecord) -> Self {
+        self.base = Some(base);
+        self
+    }
+
+    pub fn with_same_content(mut self, same_content: bool) -> Self {
+        self.same_content = Some(same_content);
+        self
+    }
+
+    fn contents_match(&self, local: &str, remote: &str) -> bool {
+        self.same_content
+            .unwrap_or_else(|| compare_checksums(local, remote))
+    }
+
     pub fn determine_sync_action(&mut self) {
         match (&self.local_md5, &self.remote_etag) {
             (Some(local), Some(remote)) => {
-                if !compare_checksums(local, remote) {
+                if !self.contents_match(local, remote) {
                     self.needs_upload = true;
                 }
             }
@@ -353,6 +1347,99 @@ impl SyncEntry {
             }
             (None, None) => {}
         }
+
+        self.action = if self.needs_upload {
+            SyncAction::Upload
+        } else if self.needs_download {
+            SyncAction::Download
+        } else {
+            SyncAction::Skip
+        };
+    }
+
+    /// Three-way comparison against `base`: a side that still matches the
+    /// base is unchanged, so only the other side's change is propagated.
+    /// When both sides changed differently the entry becomes a conflict.
+    pub fn determine_bidirectional_action(&mut self) {
+        let local = self.local_md5.as_deref();
+        let remote = self.remote_etag.as_deref();
+
+        self.action = match (&self.base, local, remote) {
+            (None, Some(local), Some(remote)) => {
+                if self.contents_match(local, remote) {
+                    SyncAction::Skip
+                } else {
+                    SyncAction::Conflict
+                }
+            }
+            (_, Some(_), None) if self.local_changed() => {
+                if self.base.is_some() {
+                    // Edited here, deleted there.
+                    SyncAction::Conflict
+                } else {
+                    SyncAction::Upload
+                }
+            }
+            (_, None, Some(_)) if self.remote_changed() => {
+                if self.base.is_some() {
+                    SyncAction::Conflict
+                } else {
+                    SyncAction::Download
+                }
+            }
+            (Some(_), Some(_), None) => SyncAction::DeleteLocal,
+            (Some(_), None, Some(_)) => SyncAction::DeleteRemote,
+            (Some(_), Some(local), Some(remote)) => {
+                match (self.local_changed(), self.remote_changed()) {
+                    (false, false) => SyncAction::Skip,
+                    (true, false) => SyncAction::Upload,
+                    (false, true) => SyncAction::Download,
+                    (true, true) if self.contents_match(local, remote) => SyncAction::Skip,
+                    (true, true) => SyncAction::Conflict,
+                }
+            }
+            _ => SyncAction::Skip,
+        };
+
+        self.needs_upload = self.action == SyncAction::Upload;
+        self.needs_download = self.action == SyncAction::Download;
+    }
+
+    /// Why `action` was chosen, in words suitable for a sync preview.
+    pub fn reason(&self) -> &'static str {
+        let has_local = self.local_md5.is_some();
+        let has_remote = self.remote_etag.is_some();
+
+        match self.action {
+            SyncAction::Upload if !has_remote => "Missing on the remote",
+            SyncAction::Upload if self.base.is_some() => "Changed locally",
+            SyncAction::Download if !has_local => "Missing locally",
+            SyncAction::Download if self.base.is_some() => "Changed on the remote",
+            SyncAction::Upload | SyncAction::Download => "Content differs",
+            SyncAction::DeleteLocal => "Deleted on the remote",
+            SyncAction::DeleteRemote => "Deleted locally",
+            SyncAction::Conflict if !has_local => "Deleted locally but changed on the remote",
+            SyncAction::Conflict if !has_remote => "Changed locally but deleted on the remote",
+            SyncAction::Conflict => "Changed on both sides",
+            SyncAction::Skip if !has_local && !has_remote => "Deleted on both sides",
+            SyncAction::Skip => "Unchanged",
+        }
+    }
+
+    fn local_changed(&self) -> bool {
+        match (&self.base, &self.local_md5) {
+            (Some(base), Some(local)) => !base.local_md5.eq_ignore_ascii_case(local),
+            (None, Some(_)) => true,
+            _ => false,
+        }
+    }
+
+    fn remote_changed(&self) -> bool {
+        match (&self.base, &self.remote_etag) {
+            (Some(base), Some(remote)) => base.remote_etag.trim_matches('"') != remote.trim_matches('"'),
+            (None, Some(_)) => true,
+            _ => false,
+        }
     }
 }
 
@@ -381,6 +1468,38 @@ mod tests {
         assert!(!compare_checksums(md5, etag));
     }
 
+    #[test]
+    fn test_multipart_part_count() {
+        assert_eq!(
+            multipart_part_count("\"d41d8cd98f00b204e9800998ecf8427e-5\""),
+            Some(5)
+        );
+        assert_eq!(
+            multipart_part_count("\"d41d8cd98f00b204e9800998ecf8427e\""),
+            None
+        );
+    }
+
+    #[test]
+    fn test_candidate_part_sizes_match_part_count() {
+        const MIB: u64 = 1024 * 1024;
+        let size = 100 * MIB;
+        let candidates = candidate_part_sizes(size, 7, &[16 * MIB]);
+        assert_eq!(candidates.first(), Some(&(16 * MIB)));
+        assert!(candidates.iter().all(|&p| (size + p - 1) / p == 7));
+        assert!(candidate_part_sizes(size, 1, &[]).contains(&(128 * MIB)));
+    }
+
+    #[test]
+    fn test_same_content_overrides_etag_comparison() {
+        let mut entry = SyncEntry::new("big.bin".to_string())
+            .with_local_md5("d41d8cd98f00b204e9800998ecf8427e".to_string())
+            .with_remote_etag("\"0123456789abcdef0123456789abcdef-3\"".to_string())
+            .with_same_content(true);
+        entry.determine_sync_action();
+        assert_eq!(entry.action, SyncAction::Skip);
+    }
+
     #[test]
     fn test_sync_entry_needs_upload() {
         let mut entry = SyncEntry::new("test.txt".to_string())
@@ -390,6 +1509,199 @@ mod tests {
         assert!(!entry.needs_download);
     }
 
+    #[test]
+    fn test_temp_files_are_ignored() {
+        assert!(is_ignored_file(Path::new("/sync/.notes.txt.swp")));
+        assert!(is_ignored_file(Path::new("/sync/~$report.docx")));
+        assert!(is_ignored_file(Path::new("/sync/.#main.rs")));
+        assert!(!is_ignored_file(Path::new("/sync/report.docx")));
+    }
+
+    #[test]
+    fn test_coalesce_drops_paths_under_a_parent() {
+        let dir = std::env::temp_dir().join(format!("s3gui-coalesce-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("nested")).unwrap();
+
+        let paths: BTreeSet<PathBuf> = [
+            dir.join("nested").join("a.txt"),
+            dir.join("nested"),
+            dir.join("gone.txt"),
+            dir.join("nested"),
+        ]
+        .into_iter()
+        .collect();
+
+        let changes = coalesce_changes(paths);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            changes,
+            vec![
+                FileChange::Removed(dir.join("gone.txt")),
+                FileChange::Changed(dir.join("nested")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_metadata_only_change_is_ignored() {
+        use notify::event::{MetadataKind, ModifyKind};
+        use notify::EventKind;
+
+        let event = Event::new(EventKind::Modify(ModifyKind::Metadata(MetadataKind::Any)))
+            .add_path(PathBuf::from("/sync/file.txt"));
+
+        assert!(changed_paths(&event).is_empty());
+    }
+
+    fn conflict(local: Option<i64>, remote: Option<i64>) -> SyncConflict {
+        SyncConflict {
+            path: "a.txt".to_string(),
+            local_size: local.map(|_| 1),
+            local_modified: local.and_then(|s| DateTime::from_timestamp(s, 0)),
+            remote_size: remote.map(|_| 1),
+            remote_modified: remote.and_then(|s| DateTime::from_timestamp(s, 0)),
+            resolution: None,
+        }
+    }
+
+    #[test]
+    fn test_newest_wins_picks_later_side() {
+        let policy = ConflictPolicy::NewestWins;
+        assert_eq!(
+            policy.resolve(&conflict(Some(200), Some(100))),
+            Some(ConflictResolution::Local)
+        );
+        assert_eq!(
+            policy.resolve(&conflict(Some(100), Some(200))),
+            Some(ConflictResolution::Remote)
+        );
+        assert_eq!(
+            policy.resolve(&conflict(None, Some(100))),
+            Some(ConflictResolution::Remote)
+        );
+    }
+
+    #[test]
+    fn test_reason_describes_action() {
+        let mut entry = SyncEntry::new("a.txt".to_string()).with_local_md5("abc".to_string());
+        entry.determine_sync_action();
+        assert_eq!(entry.reason(), "Missing on the remote");
+
+        let mut entry = SyncEntry::new("a.txt".to_string())
+            .with_remote_etag("\"aaa\"".to_string())
+            .with_base(base_record());
+        entry.determine_bidirectional_action();
+        assert_eq!(entry.action, SyncAction::DeleteRemote);
+        assert_eq!(entry.reason(), "Deleted locally");
+    }
+
+    #[test]
+    fn test_size_mtime_prefers_recorded_mtime() {
+        let local = DateTime::from_timestamp(1_700_000_000, 500_000_000).unwrap();
+        let mode = CompareMode::SizeMtime;
+
+        let truncated = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        assert!(mode.metadata_matches(10, local, 10, RemoteMtime::Recorded(truncated)));
+
+        let later = DateTime::from_timestamp(1_700_000_060, 0).unwrap();
+        assert!(!mode.metadata_matches(10, local, 10, RemoteMtime::Recorded(later)));
+        assert!(mode.metadata_matches(10, local, 10, RemoteMtime::LastModified(later)));
+        assert!(!mode.metadata_matches(10, local, 11, RemoteMtime::LastModified(later)));
+        assert!(!mode.metadata_matches(10, local, 10, RemoteMtime::Unknown));
+    }
+
+    #[test]
+    fn test_size_only_and_existence_ignore_times() {
+        let local = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        assert!(CompareMode::SizeOnly.metadata_matches(10, local, 10, RemoteMtime::Unknown));
+        assert!(!CompareMode::SizeOnly.metadata_matches(10, local, 9, RemoteMtime::Unknown));
+        assert!(CompareMode::Existence.metadata_matches(10, local, 9, RemoteMtime::Unknown));
+    }
+
+    #[test]
+    fn test_revert_snapshot_restores_previous_state() {
+        let previous = HashMap::from([("a.txt".to_string(), "\"1\"".to_string())]);
+        let mut current = HashMap::from([
+            ("a.txt".to_string(), "\"2\"".to_string()),
+            ("b.txt".to_string(), "\"3\"".to_string()),
+        ]);
+
+        revert_snapshot(&mut current, &previous, "a.txt");
+        revert_snapshot(&mut current, &previous, "b.txt");
+        assert_eq!(current, previous);
+    }
+
+    #[test]
+    fn test_ask_user_leaves_conflict_open() {
+        assert_eq!(ConflictPolicy::AskUser.resolve(&conflict(Some(1), Some(2))), None);
+    }
+
+    #[test]
+    fn test_conflict_copy_name_keeps_extension() {
+        let at = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        assert_eq!(
+            conflict_copy_name("docs/notes.txt", at),
+            "docs/notes.conflict-20231114-221320.txt"
+        );
+        assert_eq!(conflict_copy_name("Makefile", at), "Makefile.conflict-20231114-221320");
+        assert_eq!(conflict_copy_name(".env", at), ".env.conflict-20231114-221320");
+    }
+
+    fn base_record() -> SyncRecord {
+        SyncRecord {
+            local_md5: "aaa".to_string(),
+            local_size: 3,
+            local_mtime: 0,
+            remote_etag: "\"aaa\"".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_bidirectional_local_change_uploads() {
+        let mut entry = SyncEntry::new("a.txt".to_string())
+            .with_local_md5("bbb".to_string())
+            .with_remote_etag("\"aaa\"".to_string())
+            .with_base(base_record());
+        entry.determine_bidirectional_action();
+        assert_eq!(entry.action, SyncAction::Upload);
+    }
+
+    #[test]
+    fn test_bidirectional_remote_change_downloads() {
+        let mut entry = SyncEntry::new("a.txt".to_string())
+            .with_local_md5("aaa".to_string())
+            .with_remote_etag("\"ccc\"".to_string())
+            .with_base(base_record());
+        entry.determine_bidirectional_action();
+        assert_eq!(entry.action, SyncAction::Download);
+    }
+
+    #[test]
+    fn test_bidirectional_both_changed_conflicts() {
+        let mut entry = SyncEntry::new("a.txt".to_string())
+            .with_local_md5("bbb".to_string())
+            .with_remote_etag("\"ccc\"".to_string())
+            .with_base(base_record());
+        entry.determine_bidirectional_action();
+        assert_eq!(entry.action, SyncAction::Conflict);
+    }
+
+    #[test]
+    fn test_bidirectional_deletions_propagate() {
+        let mut deleted_remotely = SyncEntry::new("a.txt".to_string())
+            .with_local_md5("aaa".to_string())
+            .with_base(base_record());
+        deleted_remotely.determine_bidirectional_action();
+        assert_eq!(deleted_remotely.action, SyncAction::DeleteLocal);
+
+        let mut deleted_locally = SyncEntry::new("a.txt".to_string())
+            .with_remote_etag("\"aaa\"".to_string())
+            .with_base(base_record());
+        deleted_locally.determine_bidirectional_action();
+        assert_eq!(deleted_locally.action, SyncAction::DeleteRemote);
+    }
+
     #[test]
     fn test_sync_entry_needs_download() {
         let mut entry = SyncEntry::new("test.txt".to_string())
@@ -0,0 +1,107 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use md5::{Digest, Md5};
+use serde::{Deserialize, Serialize};
+
+/// The last state both sides agreed on for one path. A side whose current
+/// state differs from this has changed since the previous sync.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncRecord {
+    pub local_md5: String,
+    pub local_size: u64,
+    /// Local modification time in milliseconds since the Unix epoch.
+    pub local_mtime: i64,
+    pub remote_etag: String,
+}
+
+/// Per-sync state file under `~/.s3gui/sync-state`, keyed by the path
+/// relative to the sync root.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncDatabase {
+    #[serde(default)]
+    pub profile_id: String,
+    pub bucket: String,
+    pub prefix: String,
+    pub local_path: String,
+    pub records: BTreeMap<String, SyncRecord>,
+}
+
+pub fn get_state_dir() -> PathBuf {
+    let home = dirs::home_dir().expect("Failed to get home directory");
+    home.join(".s3gui").join("sync-state")
+}
+
+impl SyncDatabase {
+    /// Loads the state for a (profile, bucket, prefix, local folder) pairing,
+    /// or an empty one if it has never been synced.
+    pub fn load(profile_id: &str, bucket: &str, prefix: &str, local_path: &str) -> Result<Self> {
+        let path = Self::file_path(profile_id, bucket, prefix, local_path);
+
+        if !path.exists() {
+            return Ok(Self {
+                profile_id: profile_id.to_string(),
+                bucket: bucket.to_string(),
+                prefix: prefix.to_string(),
+                local_path: local_path.to_string(),
+                records: BTreeMap::new(),
+            });
+        }
+
+        let content = fs::read_to_string(&path).context("Failed to read sync state")?;
+        serde_json::from_str(&content).context("Failed to parse sync state")
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::file_path(
+            &self.profile_id,
+            &self.bucket,
+            &self.prefix,
+            &self.local_path,
+        );
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).context("Failed to create sync state directory")?;
+        }
+
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(&path, content).context("Failed to write sync state")
+    }
+
+    /// Two profiles can point at same-named buckets on different endpoints,
+    /// so the profile is part of the key.
+    fn file_path(profile_id: &str, bucket: &str, prefix: &str, local_path: &str) -> PathBuf {
+        let mut hasher = Md5::new();
+        hasher.update(profile_id.as_bytes());
+        hasher.update([0]);
+        hasher.update(bucket.as_bytes());
+        hasher.update([0]);
+        hasher.update(prefix.trim_end_matches('/').as_bytes());
+        hasher.update([0]);
+        hasher.update(local_path.as_bytes());
+        get_state_dir().join(format!("{:x}.json", hasher.finalize()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_file_ignores_trailing_slash_on_prefix() {
+        assert_eq!(
+            SyncDatabase::file_path("work", "bucket", "photos/", "/home/me/photos"),
+            SyncDatabase::file_path("work", "bucket", "photos", "/home/me/photos")
+        );
+        assert_ne!(
+            SyncDatabase::file_path("work", "bucket", "photos", "/home/me/photos"),
+            SyncDatabase::file_path("work", "other", "photos", "/home/me/photos")
+        );
+        assert_ne!(
+            SyncDatabase::file_path("work", "bucket", "photos", "/home/me/photos"),
+            SyncDatabase::file_path("home", "bucket", "photos", "/home/me/photos")
+        );
+    }
+}
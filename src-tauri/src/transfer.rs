@@ -0,0 +1,719 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter};
+use thiserror::Error;
+use tokio::sync::{Mutex, RwLock, Semaphore};
+use uuid::Uuid;
+
+use crate::filter::{FilterRules, PathFilter};
+use crate::s3_client::{self, TransferControl};
+
+/// How often progress events are emitted for a running transfer.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Error, Debug)]
+pub enum TransferError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Failed to parse transfer queue: {0}")]
+    Parse(#[from] serde_json::Error),
+    #[error("Transfer not found: {0}")]
+    NotFound(String),
+    #[error("Invalid filter: {0}")]
+    Filter(String),
+    #[error("Cannot {action} a transfer that is {status:?}")]
+    InvalidState {
+        action: &'static str,
+        status: TransferStatus,
+    },
+    #[error("Transfer is still stopping, try again in a moment: {0}")]
+    Stopping(String),
+}
+
+pub type Result<T> = std::result::Result<T, TransferError>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferDirection {
+    Upload,
+    Download,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferStatus {
+    Queued,
+    Running,
+    Paused,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl TransferStatus {
+    fn is_finished(self) -> bool {
+        matches!(
+            self,
+            TransferStatus::Completed | TransferStatus::Failed | TransferStatus::Cancelled
+        )
+    }
+
+    fn can_pause(self) -> bool {
+        matches!(self, TransferStatus::Queued | TransferStatus::Running)
+    }
+
+    fn can_resume(self) -> bool {
+        self == TransferStatus::Paused
+    }
+
+    fn can_cancel(self) -> bool {
+        !self.is_finished()
+    }
+
+    fn can_retry(self) -> bool {
+        matches!(self, TransferStatus::Failed | TransferStatus::Cancelled)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransferJob {
+    pub id: String,
+    pub profile_id: String,
+    pub direction: TransferDirection,
+    pub bucket: String,
+    pub key: String,
+    pub local_path: String,
+    pub status: TransferStatus,
+    pub bytes_transferred: u64,
+    pub bytes_total: u64,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransferProgressPayload {
+    pub job_id: String,
+    pub bytes_transferred: u64,
+    pub bytes_total: u64,
+}
+
+pub fn get_queue_path() -> PathBuf {
+    let home = dirs::home_dir().expect("Failed to get home directory");
+    home.join(".s3gui").join("transfers.json")
+}
+
+fn load_jobs() -> Result<Vec<TransferJob>> {
+    let path = get_queue_path();
+
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let content = fs::read_to_string(&path)?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+async fn save_jobs(content: String) -> Result<()> {
+    let path = get_queue_path();
+
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+
+    tokio::fs::write(&path, content).await?;
+    Ok(())
+}
+
+/// Puts whatever was running when the app closed back in line.
+fn requeue_interrupted(jobs: &mut [TransferJob]) {
+    for job in jobs.iter_mut() {
+        if job.status == TransferStatus::Running {
+            job.status = TransferStatus::Queued;
+        }
+    }
+}
+
+/// Moves a job to `to` if `allowed` accepts its current status.
+fn apply_transition(
+    jobs: &mut [TransferJob],
+    job_id: &str,
+    action: &'static str,
+    allowed: impl Fn(TransferStatus) -> bool,
+    to: TransferStatus,
+) -> Result<TransferJob> {
+    let job = jobs
+        .iter_mut()
+        .find(|j| j.id == job_id)
+        .ok_or_else(|| TransferError::NotFound(job_id.to_string()))?;
+
+    if !allowed(job.status) {
+        return Err(TransferError::InvalidState {
+            action,
+            status: job.status,
+        });
+    }
+
+    job.status = to;
+    if to == TransferStatus::Queued {
+        job.error = None;
+        job.finished_at = None;
+    }
+    if to == TransferStatus::Cancelled {
+        job.finished_at = Some(Utc::now());
+    }
+    Ok(job.clone())
+}
+
+/// A paused or cancelled job's worker only lets go of it once the
+/// transfer notices the stop. Starting another worker before then would
+/// have two of them writing the same files.
+fn ensure_stopped(controls: &HashMap<String, Arc<TransferControl>>, job_id: &str) -> Result<()> {
+    if controls.contains_key(job_id) {
+        return Err(TransferError::Stopping(job_id.to_string()));
+    }
+    Ok(())
+}
+
+/// Queues uploads and downloads and runs them in the background, a few at a
+/// time. The queue is written to disk on every status change so unfinished
+/// transfers are picked up again after a restart.
+#[derive(Clone)]
+pub struct TransferManager {
+    jobs: Arc<RwLock<Vec<TransferJob>>>,
+    /// Held from taking a snapshot of `jobs` until it is written, so saves
+    /// land in order without blocking the queue on disk.
+    saving: Arc<Mutex<()>>,
+    controls: Arc<RwLock<HashMap<String, Arc<TransferControl>>>>,
+    semaphore: Arc<Semaphore>,
+    app_handle: AppHandle,
+}
+
+impl TransferManager {
+    pub fn new(app_handle: AppHandle) -> Self {
+        let mut jobs = load_jobs().unwrap_or_default();
+        requeue_interrupted(&mut jobs);
+
+        let max_concurrent = crate::config::load_config()
+            .map(|c| c.transfer.max_concurrent_transfers)
+            .unwrap_or(3)
+            .max(1);
+
+        Self {
+            jobs: Arc::new(RwLock::new(jobs)),
+            saving: Arc::new(Mutex::new(())),
+            controls: Arc::new(RwLock::new(HashMap::new())),
+            semaphore: Arc::new(Semaphore::new(max_concurrent)),
+            app_handle,
+        }
+    }
+
+    /// Starts workers for every job that was still queued when the queue was
+    /// loaded.
+    pub async fn start_pending(&self) {
+        let queued: Vec<String> = self
+            .jobs
+            .read()
+            .await
+            .iter()
+            .filter(|j| j.status == TransferStatus::Queued)
+            .map(|j| j.id.clone())
+            .collect();
+
+        for id in queued {
+            self.spawn_job(id);
+        }
+    }
+
+    pub async fn enqueue_upload(
+        &self,
+        profile_id: &str,
+        bucket: &str,
+        key: &str,
+        local_path: &str,
+    ) -> Result<TransferJob> {
+        let job = Self::upload_job(profile_id, bucket, key, local_path)?;
+        let mut jobs = self.enqueue(vec![job]).await?;
+        Ok(jobs.remove(0))
+    }
+
+    /// Queues one upload per file under `folder_path`, keyed relative to it.
+    pub async fn enqueue_folder_upload(
+        &self,
+        profile_id: &str,
+        bucket: &str,
+        prefix: &str,
+        folder_path: &str,
+        filters: &FilterRules,
+    ) -> Result<Vec<TransferJob>> {
+        let filter = PathFilter::new(Path::new(folder_path), filters)
+            .map_err(|e| TransferError::Filter(format!("{:#}", e)))?;
+        let mut jobs = Vec::new();
+
+        for (relative_path, path) in filter.files() {
+            let key = s3_client::join_key(prefix, &relative_path);
+            let local_path = path.to_string_lossy().to_string();
+
+            jobs.push(Self::upload_job(profile_id, bucket, &key, &local_path)?);
+        }
+
+        // Queued together so the queue is saved once, not once per file.
+        self.enqueue(jobs).await
+    }
+
+    pub async fn enqueue_download(
+        &self,
+        profile_id: &str,
+        bucket: &str,
+        key: &str,
+        local_path: &str,
+    ) -> Result<TransferJob> {
+        let job = Self::new_job(
+            profile_id,
+            TransferDirection::Download,
+            bucket,
+            key,
+            local_path,
+            0,
+        );
+        let mut jobs = self.enqueue(vec![job]).await?;
+        Ok(jobs.remove(0))
+    }
+
+    pub async fn list_transfers(&self) -> Vec<TransferJob> {
+        self.jobs.read().await.clone()
+    }
+
+    pub async fn pause(&self, job_id: &str) -> Result<TransferJob> {
+        let job = self
+            .transition(
+                job_id,
+                "pause",
+                TransferStatus::can_pause,
+                TransferStatus::Paused,
+            )
+            .await?;
+
+        // Downloads keep their partial file, so resuming picks up where the
+        // pause left off.
+        self.signal_cancel(job_id).await;
+        Ok(job)
+    }
+
+    pub async fn resume(&self, job_id: &str) -> Result<TransferJob> {
+        ensure_stopped(&*self.controls.read().await, job_id)?;
+        let job = self
+            .transition(
+                job_id,
+                "resume",
+                TransferStatus::can_resume,
+                TransferStatus::Queued,
+            )
+            .await?;
+        self.spawn_job(job.id.clone());
+        Ok(job)
+    }
+
+    pub async fn cancel(&self, job_id: &str) -> Result<TransferJob> {
+        let (job, was_running) = self
+            .transition_with_worker(
+                job_id,
+                "cancel",
+                TransferStatus::can_cancel,
+                TransferStatus::Cancelled,
+            )
+            .await?;
+
+        self.signal_cancel(job_id).await;
+        // A running download cleans up after itself once it notices.
+        if !was_running && job.direction == TransferDirection::Download {
+            s3_client::discard_partial_download(&job.local_path).await;
+        }
+        Ok(job)
+    }
+
+    pub async fn retry(&self, job_id: &str) -> Result<TransferJob> {
+        ensure_stopped(&*self.controls.read().await, job_id)?;
+        let job = self
+            .transition(
+                job_id,
+                "retry",
+                TransferStatus::can_retry,
+                TransferStatus::Queued,
+            )
+            .await?;
+        self.spawn_job(job.id.clone());
+        Ok(job)
+    }
+
+    /// Drops completed, failed and cancelled jobs from the queue.
+    pub async fn clear_finished(&self) -> Result<()> {
+        let _saving = self.saving.lock().await;
+        let content = {
+            let mut jobs = self.jobs.write().await;
+            jobs.retain(|j| !j.status.is_finished());
+            serde_json::to_string_pretty(&*jobs)?
+        };
+        save_jobs(content).await
+    }
+
+    fn upload_job(
+        profile_id: &str,
+        bucket: &str,
+        key: &str,
+        local_path: &str,
+    ) -> Result<TransferJob> {
+        let size = fs::metadata(local_path)?.len();
+        Ok(Self::new_job(
+            profile_id,
+            TransferDirection::Upload,
+            bucket,
+            key,
+            local_path,
+            size,
+        ))
+    }
+
+    fn new_job(
+        profile_id: &str,
+        direction: TransferDirection,
+        bucket: &str,
+        key: &str,
+        local_path: &str,
+        bytes_total: u64,
+    ) -> TransferJob {
+        TransferJob {
+            id: Uuid::new_v4().to_string(),
+            profile_id: profile_id.to_string(),
+            direction,
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            local_path: local_path.to_string(),
+            status: TransferStatus::Queued,
+            bytes_transferred: 0,
+            bytes_total,
+            error: None,
+            created_at: Utc::now(),
+            finished_at: None,
+        }
+    }
+
+    async fn enqueue(&self, new_jobs: Vec<TransferJob>) -> Result<Vec<TransferJob>> {
+        {
+            let _saving = self.saving.lock().await;
+            let content = {
+                let mut jobs = self.jobs.write().await;
+                jobs.extend(new_jobs.iter().cloned());
+                serde_json::to_string_pretty(&*jobs)?
+            };
+            save_jobs(content).await?;
+        }
+
+        for job in &new_jobs {
+            self.emit_updated(job);
+            self.spawn_job(job.id.clone());
+        }
+        Ok(new_jobs)
+    }
+
+    /// Moves a job to `to` if `allowed` accepts its current status, then
+    /// persists the queue and notifies the UI.
+    async fn transition(
+        &self,
+        job_id: &str,
+        action: &'static str,
+        allowed: impl Fn(TransferStatus) -> bool,
+        to: TransferStatus,
+    ) -> Result<TransferJob> {
+        self.transition_with_worker(job_id, action, allowed, to)
+            .await
+            .map(|(job, _)| job)
+    }
+
+    /// `transition`, also telling whether a worker was running the job at
+    /// the moment its status changed.
+    async fn transition_with_worker(
+        &self,
+        job_id: &str,
+        action: &'static str,
+        allowed: impl Fn(TransferStatus) -> bool,
+        to: TransferStatus,
+    ) -> Result<(TransferJob, bool)> {
+        let _saving = self.saving.lock().await;
+        let (job, running, content) = {
+            let mut jobs = self.jobs.write().await;
+            // Workers register and unregister while holding `jobs`, so this
+            // can't change before the new status is in place.
+            let running = self.controls.read().await.contains_key(job_id);
+            let job = apply_transition(&mut jobs, job_id, action, allowed, to)?;
+            (job, running, serde_json::to_string_pretty(&*jobs)?)
+        };
+        save_jobs(content).await?;
+
+        self.emit_updated(&job);
+        Ok((job, running))
+    }
+
+    async fn signal_cancel(&self, job_id: &str) {
+        if let Some(control) = self.controls.read().await.get(job_id) {
+            control.cancel();
+        }
+    }
+
+    fn spawn_job(&self, job_id: String) {
+        let manager = self.clone();
+        tauri::async_runtime::spawn(async move {
+            manager.run_job(&job_id).await;
+        });
+    }
+
+    async fn run_job(&self, job_id: &str) {
+        let _permit = match self.semaphore.acquire().await {
+            Ok(permit) => permit,
+            Err(_) => return,
+        };
+
+        // The job may have been paused, cancelled or picked up by another
+        // worker while this one waited for a slot. The control is registered
+        // under the same lock, so a pause can't slip in before it exists.
+        let control = Arc::new(TransferControl::default());
+        let job = {
+            let _saving = self.saving.lock().await;
+            let (job, content) = {
+                let mut jobs = self.jobs.write().await;
+                let Some(job) = jobs.iter_mut().find(|j| j.id == job_id) else {
+                    return;
+                };
+                if job.status != TransferStatus::Queued {
+                    return;
+                }
+                job.status = TransferStatus::Running;
+                self.controls
+                    .write()
+                    .await
+                    .insert(job.id.clone(), control.clone());
+                let job = job.clone();
+                (job, serde_json::to_string_pretty(&*jobs))
+            };
+            if let Ok(content) = content {
+                let _ = save_jobs(content).await;
+            }
+            job
+        };
+        self.emit_updated(&job);
+
+        let transfer = Self::execute(&job, &control);
+        tokio::pin!(transfer);
+        let mut ticker = tokio::time::interval(PROGRESS_INTERVAL);
+        let result = loop {
+            tokio::select! {
+                result = &mut transfer => break result,
+                _ = ticker.tick() => self.emit_progress(&job.id, &control),
+            }
+        };
+        self.emit_progress(&job.id, &control);
+
+        let job = {
+            let _saving = self.saving.lock().await;
+            let (job, content) = {
+                let mut jobs = self.jobs.write().await;
+                {
+                    let mut controls = self.controls.write().await;
+                    if controls
+                        .get(job_id)
+                        .is_some_and(|current| Arc::ptr_eq(current, &control))
+                    {
+                        controls.remove(job_id);
+                    }
+                }
+                let Some(job) = jobs.iter_mut().find(|j| j.id == job_id) else {
+                    return;
+                };
+
+                job.bytes_transferred = control.transferred();
+                job.bytes_total = control.total().max(job.bytes_total);
+
+                match result {
+                    Ok(()) => {
+                        job.status = TransferStatus::Completed;
+                        job.finished_at = Some(Utc::now());
+                    }
+                    // Stopped on request; pause/cancel already set the status.
+                    Err(_)
+                        if matches!(
+                            job.status,
+                            TransferStatus::Paused | TransferStatus::Cancelled
+                        ) => {}
+                    Err(e) => {
+                        job.status = TransferStatus::Failed;
+                        job.error = Some(format!("{:#}", e));
+                        job.finished_at = Some(Utc::now());
+                    }
+                }
+
+                let job = job.clone();
+                (job, serde_json::to_string_pretty(&*jobs))
+            };
+            if let Ok(content) = content {
+                let _ = save_jobs(content).await;
+            }
+            job
+        };
+
+        if job.status == TransferStatus::Cancelled && job.direction == TransferDirection::Download {
+            s3_client::discard_partial_download(&job.local_path).await;
+        }
+
+        self.emit_updated(&job);
+    }
+
+    async fn execute(job: &TransferJob, control: &TransferControl) -> anyhow::Result<()> {
+        let client = s3_client::client_for_profile(&job.profile_id).await?;
+
+        match job.direction {
+            TransferDirection::Upload => client
+                .upload_file_tracked(&job.bucket, &job.key, &job.local_path, control)
+                .await
+                .map(|_| ()),
+            TransferDirection::Download => {
+                client
+                    .download_object_tracked(&job.bucket, &job.key, &job.local_path, control)
+                    .await
+            }
+        }
+    }
+
+    fn emit_updated(&self, job: &TransferJob) {
+        let _ = self.app_handle.emit("transfer-updated", job.clone());
+    }
+
+    fn emit_progress(&self, job_id: &str, control: &TransferControl) {
+        let _ = self.app_handle.emit(
+            "transfer-progress",
+            TransferProgressPayload {
+                job_id: job_id.to_string(),
+                bytes_transferred: control.transferred(),
+                bytes_total: control.total(),
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(status: TransferStatus) -> TransferJob {
+        let mut job = TransferManager::new_job(
+            "profile",
+            TransferDirection::Download,
+            "bucket",
+            "key",
+            "/tmp/key",
+            0,
+        );
+        job.status = status;
+        job
+    }
+
+    fn allowed(
+        check: fn(TransferStatus) -> bool,
+        from: TransferStatus,
+        to: TransferStatus,
+    ) -> bool {
+        let mut jobs = vec![job(from)];
+        let id = jobs[0].id.clone();
+        apply_transition(&mut jobs, &id, "change", check, to).is_ok()
+    }
+
+    #[test]
+    fn test_transitions_follow_the_job_status() {
+        use TransferStatus::*;
+
+        for status in [Queued, Running, Paused, Completed, Failed, Cancelled] {
+            let pause = allowed(TransferStatus::can_pause, status, Paused);
+            assert_eq!(pause, matches!(status, Queued | Running));
+
+            let resume = allowed(TransferStatus::can_resume, status, Queued);
+            assert_eq!(resume, status == Paused);
+
+            let retry = allowed(TransferStatus::can_retry, status, Queued);
+            assert_eq!(retry, matches!(status, Failed | Cancelled));
+
+            let cancel = allowed(TransferStatus::can_cancel, status, Cancelled);
+            assert_eq!(cancel, matches!(status, Queued | Running | Paused));
+        }
+    }
+
+    #[test]
+    fn test_retry_clears_the_previous_failure() {
+        let mut failed = job(TransferStatus::Failed);
+        failed.error = Some("Access Denied".to_string());
+        failed.finished_at = Some(Utc::now());
+        let id = failed.id.clone();
+        let mut jobs = vec![failed];
+
+        let retried = apply_transition(
+            &mut jobs,
+            &id,
+            "retry",
+            TransferStatus::can_retry,
+            TransferStatus::Queued,
+        )
+        .unwrap();
+
+        assert_eq!(retried.status, TransferStatus::Queued);
+        assert!(retried.error.is_none());
+        assert!(retried.finished_at.is_none());
+        assert!(matches!(
+            apply_transition(
+                &mut jobs,
+                "missing",
+                "retry",
+                TransferStatus::can_retry,
+                TransferStatus::Queued
+            ),
+            Err(TransferError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_interrupted_jobs_are_queued_again() {
+        let mut jobs = vec![
+            job(TransferStatus::Running),
+            job(TransferStatus::Paused),
+            job(TransferStatus::Completed),
+        ];
+
+        requeue_interrupted(&mut jobs);
+
+        let statuses: Vec<TransferStatus> = jobs.iter().map(|j| j.status).collect();
+        assert_eq!(
+            statuses,
+            [
+                TransferStatus::Queued,
+                TransferStatus::Paused,
+                TransferStatus::Completed
+            ]
+        );
+    }
+
+    #[test]
+    fn test_a_job_still_stopping_cannot_restart() {
+        let mut controls = HashMap::new();
+        assert!(ensure_stopped(&controls, "job").is_ok());
+
+        controls.insert("job".to_string(), Arc::new(TransferControl::default()));
+        assert!(matches!(
+            ensure_stopped(&controls, "job"),
+            Err(TransferError::Stopping(_))
+        ));
+        assert!(ensure_stopped(&controls, "other").is_ok());
+    }
+}
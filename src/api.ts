@@ -3,10 +3,26 @@ import type {
   Profile,
   BucketInfo,
   ListObjectsResult,
+  BucketVersioning,
+  ListVersionsResult,
   ObjectMetadata,
+  SyncOptions,
+  FilterRules,
+  RemoteDeletePolicy,
+  SyncPlan,
   SyncResult,
+  ConflictResolution,
   SyncState,
+  SyncJob,
+  SyncRun,
+  RunQuery,
+  ReportFormat,
   DeleteObjectsResult,
+  PrefixSummary,
+  CopyOverrides,
+  RenamePrefixResult,
+  CopyPrefixResult,
+  TransferJob,
 } from "./types";
 
 // Profile functions
@@ -39,6 +55,16 @@ export async function createBucket(
   return invoke("create_bucket", { profileId, name, public: isPublic });
 }
 
+// `confirmName` must repeat the bucket name.
+export async function deleteBucket(
+  profileId: string,
+  name: string,
+  confirmName: string,
+  emptyFirst: boolean = false
+): Promise<void> {
+  return invoke("delete_bucket", { profileId, name, confirmName, emptyFirst });
+}
+
 export async function listObjects(
   profileId: string,
   bucket: string,
@@ -55,6 +81,56 @@ export async function listObjects(
   });
 }
 
+export async function getBucketVersioning(
+  profileId: string,
+  bucket: string
+): Promise<BucketVersioning> {
+  return invoke("get_bucket_versioning", { profileId, bucket });
+}
+
+export async function setBucketVersioning(
+  profileId: string,
+  bucket: string,
+  enabled: boolean
+): Promise<void> {
+  return invoke("set_bucket_versioning", { profileId, bucket, enabled });
+}
+
+export async function listObjectVersions(
+  profileId: string,
+  bucket: string,
+  prefix?: string,
+  keyMarker?: string,
+  versionIdMarker?: string,
+  maxKeys: number = 50
+): Promise<ListVersionsResult> {
+  return invoke("list_object_versions", {
+    profileId,
+    bucket,
+    prefix: prefix || null,
+    keyMarker: keyMarker || null,
+    versionIdMarker: versionIdMarker || null,
+    maxKeys,
+  });
+}
+
+export async function restoreObjectVersion(
+  profileId: string,
+  bucket: string,
+  key: string,
+  versionId: string
+): Promise<string | null> {
+  return invoke("restore_object_version", { profileId, bucket, key, versionId });
+}
+
+export async function undeleteObject(
+  profileId: string,
+  bucket: string,
+  key: string
+): Promise<void> {
+  return invoke("undelete_object", { profileId, bucket, key });
+}
+
 export async function createFolder(
   profileId: string,
   bucket: string,
@@ -67,9 +143,16 @@ export async function downloadObject(
   profileId: string,
   bucket: string,
   key: string,
-  localPath: string
+  localPath: string,
+  versionId?: string
 ): Promise<void> {
-  return invoke("download_object", { profileId, bucket, key, localPath });
+  return invoke("download_object", {
+    profileId,
+    bucket,
+    key,
+    localPath,
+    versionId: versionId ?? null,
+  });
 }
 
 export async function uploadFile(
@@ -94,9 +177,10 @@ export async function uploadFolder(
   profileId: string,
   bucket: string,
   prefix: string,
-  folderPath: string
+  folderPath: string,
+  filters?: FilterRules
 ): Promise<void> {
-  return invoke("upload_folder", { profileId, bucket, prefix, folderPath });
+  return invoke("upload_folder", { profileId, bucket, prefix, folderPath, filters });
 }
 
 export async function deleteObject(
@@ -115,6 +199,86 @@ export async function deleteObjects(
   return invoke("delete_objects", { profileId, bucket, keys });
 }
 
+export async function previewDeletePrefix(
+  profileId: string,
+  bucket: string,
+  prefix: string
+): Promise<PrefixSummary> {
+  return invoke("preview_delete_prefix", { profileId, bucket, prefix });
+}
+
+export async function deletePrefix(
+  profileId: string,
+  bucket: string,
+  prefix: string
+): Promise<DeleteObjectsResult> {
+  return invoke("delete_prefix", { profileId, bucket, prefix });
+}
+
+export async function copyObject(
+  profileId: string,
+  sourceBucket: string,
+  sourceKey: string,
+  bucket: string,
+  key: string,
+  overrides?: CopyOverrides
+): Promise<string | null> {
+  return invoke("copy_object", {
+    profileId,
+    sourceBucket,
+    sourceKey,
+    bucket,
+    key,
+    overrides: overrides ?? null,
+  });
+}
+
+export async function moveObject(
+  profileId: string,
+  sourceBucket: string,
+  sourceKey: string,
+  bucket: string,
+  key: string,
+  overrides?: CopyOverrides
+): Promise<string | null> {
+  return invoke("move_object", {
+    profileId,
+    sourceBucket,
+    sourceKey,
+    bucket,
+    key,
+    overrides: overrides ?? null,
+  });
+}
+
+export async function renamePrefix(
+  profileId: string,
+  bucket: string,
+  fromPrefix: string,
+  toPrefix: string
+): Promise<RenamePrefixResult> {
+  return invoke("rename_prefix", { profileId, bucket, fromPrefix, toPrefix });
+}
+
+// A `sourceKey` ending in "/" copies everything under that prefix.
+export async function copyBetweenProfiles(
+  sourceProfileId: string,
+  sourceBucket: string,
+  sourceKey: string,
+  profileId: string,
+  bucket: string,
+  prefix: string
+): Promise<CopyPrefixResult> {
+  return invoke("copy_between_profiles", {
+    sourceProfileId,
+    sourceBucket,
+    sourceKey,
+    profileId,
+    bucket,
+    prefix,
+  });
+}
+
 export async function presignUrl(
   profileId: string,
   bucket: string,
@@ -129,7 +293,8 @@ export async function syncFolder(
   bucket: string,
   prefix: string,
   localPath: string,
-  direction: string
+  direction: string,
+  options?: SyncOptions
 ): Promise<SyncResult> {
   return invoke("sync_folder", {
     profileId,
@@ -137,6 +302,48 @@ export async function syncFolder(
     prefix,
     localPath,
     direction,
+    options,
+  });
+}
+
+export async function planSync(
+  profileId: string,
+  bucket: string,
+  prefix: string,
+  localPath: string,
+  direction: string,
+  options?: SyncOptions
+): Promise<SyncPlan> {
+  return invoke("plan_sync", {
+    profileId,
+    bucket,
+    prefix,
+    localPath,
+    direction,
+    options,
+  });
+}
+
+export async function executeSyncPlan(
+  profileId: string,
+  plan: SyncPlan
+): Promise<SyncResult> {
+  return invoke("execute_sync_plan", { profileId, plan });
+}
+
+export async function resolveSyncConflicts(
+  profileId: string,
+  bucket: string,
+  prefix: string,
+  localPath: string,
+  resolutions: Record<string, ConflictResolution>
+): Promise<SyncResult> {
+  return invoke("resolve_sync_conflicts", {
+    profileId,
+    bucket,
+    prefix,
+    localPath,
+    resolutions,
   });
 }
 
@@ -156,9 +363,15 @@ export interface PreviewData {
 export async function getObjectPreview(
   profileId: string,
   bucket: string,
-  key: string
+  key: string,
+  versionId?: string
 ): Promise<PreviewData> {
-  return invoke("get_object_preview", { profileId, bucket, key });
+  return invoke("get_object_preview", {
+    profileId,
+    bucket,
+    key,
+    versionId: versionId ?? null,
+  });
 }
 
 // KeepSync functions
@@ -166,13 +379,21 @@ export async function startKeepSync(
   profileId: string,
   bucket: string,
   prefix: string,
-  localPath: string
+  localPath: string,
+  debounceMs?: number,
+  filters?: FilterRules,
+  pollIntervalSecs?: number,
+  remoteDeletes?: RemoteDeletePolicy
 ): Promise<string> {
   return invoke("start_keep_sync", {
     profileId,
     bucket,
     prefix,
     localPath,
+    debounceMs: debounceMs ?? null,
+    filters,
+    pollIntervalSecs: pollIntervalSecs ?? null,
+    remoteDeletes: remoteDeletes ?? null,
   });
 }
 
@@ -180,6 +401,103 @@ export async function stopKeepSync(syncId: string): Promise<void> {
   return invoke("stop_keep_sync", { syncId });
 }
 
+export async function pollKeepSync(syncId: string): Promise<void> {
+  return invoke("poll_keep_sync", { syncId });
+}
+
 export async function getActiveSyncs(): Promise<SyncState[]> {
   return invoke("get_active_syncs");
 }
+
+// Scheduled sync job functions
+export async function listSyncJobs(): Promise<SyncJob[]> {
+  return invoke("list_sync_jobs");
+}
+
+export async function saveSyncJob(job: SyncJob): Promise<SyncJob> {
+  return invoke("save_sync_job", { job });
+}
+
+export async function deleteSyncJob(id: string): Promise<void> {
+  return invoke("delete_sync_job", { id });
+}
+
+export async function runSyncJob(jobId: string): Promise<SyncRun> {
+  return invoke("run_sync_job", { jobId });
+}
+
+// Sync run log functions
+export async function listSyncRuns(query?: RunQuery): Promise<SyncRun[]> {
+  return invoke("list_sync_runs", { query: query ?? null });
+}
+
+export async function getSyncRun(runId: string): Promise<SyncRun> {
+  return invoke("get_sync_run", { runId });
+}
+
+export async function exportSyncRuns(
+  runIds: string[],
+  format: ReportFormat,
+  path: string
+): Promise<void> {
+  return invoke("export_sync_runs", { runIds, format, path });
+}
+
+// Transfer queue functions
+export async function enqueueUploads(
+  profileId: string,
+  bucket: string,
+  prefix: string,
+  filePaths: string[]
+): Promise<TransferJob[]> {
+  return invoke("enqueue_uploads", { profileId, bucket, prefix, filePaths });
+}
+
+export async function enqueueFolderUpload(
+  profileId: string,
+  bucket: string,
+  prefix: string,
+  folderPath: string,
+  filters?: FilterRules
+): Promise<TransferJob[]> {
+  return invoke("enqueue_folder_upload", {
+    profileId,
+    bucket,
+    prefix,
+    folderPath,
+    filters,
+  });
+}
+
+export async function enqueueDownload(
+  profileId: string,
+  bucket: string,
+  key: string,
+  localPath: string
+): Promise<TransferJob> {
+  return invoke("enqueue_download", { profileId, bucket, key, localPath });
+}
+
+export async function listTransfers(): Promise<TransferJob[]> {
+  return invoke("list_transfers");
+}
+
+export async function pauseTransfer(jobId: string): Promise<TransferJob> {
+  return invoke("pause_transfer", { jobId });
+}
+
+export async function resumeTransfer(jobId: string): Promise<TransferJob> {
+  return invoke("resume_transfer", { jobId });
+}
+
+export async function cancelTransfer(jobId: string): Promise<TransferJob> {
+  return invoke("cancel_transfer", { jobId });
+}
+
+export async function retryTransfer(jobId: string): Promise<TransferJob> {
+  return invoke("retry_transfer", { jobId });
+}
+
+export async function clearFinishedTransfers(): Promise<void> {
+  return invoke("clear_finished_transfers");
+}
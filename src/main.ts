@@ -16,6 +16,8 @@ import type {
   Task,
   TaskStatus,
   TaskType,
+  TransferJob,
+  TransferProgressPayload,
 } from "./types";
 
 // State
@@ -41,6 +43,7 @@ let searchPrefix = "";
 let searchTimeout: number | null = null;
 let tasks: Task[] = [];
 let taskIdCounter = 0;
+let transfers: TransferJob[] = [];
 let selectedKeys: Set<string> = new Set();
 let sortField: "name" | "size" | "modified" = "name";
 let sortDirection: "asc" | "desc" = "asc";
@@ -85,6 +88,8 @@ const clearCompletedBtn = document.getElementById("clear-completed-btn")!;
 const closeTaskPanelBtn = document.getElementById("close-task-panel-btn")!;
 const taskProgressText = document.getElementById("task-progress-text")!;
 const taskProgressFill = document.getElementById("task-progress-fill")!;
+const transferListEl = document.getElementById("transfer-list")!;
+const clearFinishedTransfersBtn = document.getElementById("clear-finished-transfers-btn")!;
 const deleteSelectedBtn = document.getElementById("delete-selected-btn")!;
 const selectedCountEl = document.getElementById("selected-count")!;
 const selectAllCheckbox = document.getElementById("select-all-checkbox") as HTMLInputElement;
@@ -144,6 +149,7 @@ async function init() {
   setupTauriEventListeners();
   addProviderAndStyleDropdowns();
   updateSortIndicators();
+  await loadTransfers();
   await restoreLastSelection();
 }
 
@@ -913,7 +919,7 @@ async function uploadFiles() {
   
   if (!files || files.length === 0) return;
   
-  await uploadFilesWithTasks(files as string[]);
+  await enqueueFileUploads(files as string[]);
 }
 
 async function uploadDroppedFiles(filePaths: string[]) {
@@ -924,60 +930,17 @@ async function uploadDroppedFiles(filePaths: string[]) {
   
   if (filePaths.length === 0) return;
   
-  await uploadFilesWithTasks(filePaths);
+  await enqueueFileUploads(filePaths);
 }
 
-const MAX_CONCURRENT_UPLOADS = 20;
-
-async function uploadFilesWithTasks(filePaths: string[]) {
+async function enqueueFileUploads(filePaths: string[]) {
   if (!currentProfileId || !currentBucket) return;
   
-  const fileNames = filePaths.map(filePath => filePath.split(/[/\\]/).pop() || filePath);
-  const createdTasks = addTasksBatch("upload", fileNames);
-  
-  const uploadItems = filePaths.map((filePath, index) => ({
-    filePath,
-    task: createdTasks[index],
-  }));
-  
-  const profileId = currentProfileId!;
-  const bucket = currentBucket!;
-  const prefix = currentPrefix;
-  
-  await runWithConcurrency(uploadItems, MAX_CONCURRENT_UPLOADS, async ({ filePath, task }) => {
-    updateTaskStatus(task.id, "running");
-    try {
-      await api.uploadFile(profileId, bucket, prefix, filePath);
-      updateTaskStatus(task.id, "completed");
-    } catch (err) {
-      updateTaskStatus(task.id, "failed", String(err));
-    }
-  });
-  
-  await loadObjects();
-}
-
-async function runWithConcurrency<T>(
-  items: T[],
-  concurrency: number,
-  fn: (item: T) => Promise<void>
-): Promise<void> {
-  const queue = [...items];
-  const running: Promise<void>[] = [];
-  
-  while (queue.length > 0 || running.length > 0) {
-    while (running.length < concurrency && queue.length > 0) {
-      const item = queue.shift()!;
-      const promise = fn(item).finally(() => {
-        const index = running.indexOf(promise);
-        if (index > -1) running.splice(index, 1);
-      });
-      running.push(promise);
-    }
-    
-    if (running.length > 0) {
-      await Promise.race(running);
-    }
+  try {
+    const jobs = await api.enqueueUploads(currentProfileId, currentBucket, currentPrefix, filePaths);
+    addTransfers(jobs);
+  } catch (err) {
+    showError(`Failed to queue uploads: ${err}`);
   }
 }
 
@@ -995,13 +958,10 @@ async function uploadFolder() {
   if (!folder) return;
   
   try {
-    setLoading(true);
-    await api.uploadFolder(currentProfileId, currentBucket, currentPrefix, folder as string);
-    await loadObjects();
+    const jobs = await api.enqueueFolderUpload(currentProfileId, currentBucket, currentPrefix, folder as string);
+    addTransfers(jobs);
   } catch (err) {
     showError(`Failed to upload folder: ${err}`);
-  } finally {
-    setLoading(false);
   }
 }
 
@@ -1017,12 +977,10 @@ async function downloadObject(key: string) {
   if (!savePath) return;
   
   try {
-    setLoading(true);
-    await api.downloadObject(currentProfileId, currentBucket, key, savePath);
+    const job = await api.enqueueDownload(currentProfileId, currentBucket, key, savePath);
+    addTransfers([job]);
   } catch (err) {
     showError(`Failed to download: ${err}`);
-  } finally {
-    setLoading(false);
   }
 }
 
@@ -1179,8 +1137,9 @@ async function syncWithLocal() {
       folder as string,
       "local_to_remote"
     );
-    updateTaskStatus(task.id, "completed");
-    showError(`Sync complete: ${result.uploaded} uploaded, ${result.downloaded} downloaded, ${result.skipped} skipped`);
+    const failed = result.errors.length ? `, ${result.errors.length} failed` : "";
+    updateTaskStatus(task.id, result.errors.length ? "failed" : "completed", result.errors[0]?.message);
+    showError(`Sync complete: ${result.uploaded} uploaded, ${result.downloaded} downloaded, ${result.skipped} skipped${failed}`);
     await loadObjects();
   } catch (err) {
     updateTaskStatus(task.id, "failed", String(err));
@@ -1249,19 +1208,6 @@ function addTask(type: TaskType, fileName: string): Task {
   return task;
 }
 
-function addTasksBatch(type: TaskType, fileNames: string[]): Task[] {
-  const newTasks = fileNames.map(fileName => ({
-    id: generateTaskId(),
-    type,
-    fileName,
-    status: "pending" as TaskStatus,
-  }));
-  tasks.unshift(...newTasks);
-  showTaskPanel();
-  scheduleRender();
-  return newTasks;
-}
-
 function updateTaskStatus(taskId: string, status: TaskStatus, error?: string) {
   const task = tasks.find(t => t.id === taskId);
   if (task) {
@@ -1274,7 +1220,7 @@ function updateTaskStatus(taskId: string, status: TaskStatus, error?: string) {
 function clearCompletedTasks() {
   tasks = tasks.filter(t => t.status !== "completed" && t.status !== "failed");
   renderTasks();
-  if (tasks.length === 0) {
+  if (tasks.length === 0 && transfers.length === 0) {
     hideTaskPanel();
   }
 }
@@ -1393,6 +1339,198 @@ function renderTasks() {
   taskListEl.innerHTML = html;
 }
 
+// Transfer queue
+let transferRenderScheduled = false;
+let objectsRefreshTimeout: number | null = null;
+
+function isTransferFinished(job: TransferJob): boolean {
+  return job.status === "completed" || job.status === "failed" || job.status === "cancelled";
+}
+
+async function loadTransfers() {
+  try {
+    transfers = (await api.listTransfers()).reverse();
+    if (transfers.some(job => !isTransferFinished(job))) {
+      showTaskPanel();
+    }
+    renderTransfers();
+  } catch (err) {
+    showError(`Failed to load transfers: ${err}`);
+  }
+}
+
+function upsertTransfer(job: TransferJob) {
+  const index = transfers.findIndex(t => t.id === job.id);
+  if (index > -1) {
+    transfers[index] = job;
+  } else {
+    transfers.unshift(job);
+  }
+}
+
+function addTransfers(jobs: TransferJob[]) {
+  jobs.forEach(upsertTransfer);
+  showTaskPanel();
+  scheduleTransferRender();
+}
+
+function handleTransferUpdated(job: TransferJob) {
+  upsertTransfer(job);
+  scheduleTransferRender();
+  
+  if (job.direction === "upload" && job.status === "completed" && job.bucket === currentBucket) {
+    scheduleObjectsRefresh();
+  }
+}
+
+function handleTransferProgress(progress: TransferProgressPayload) {
+  const job = transfers.find(t => t.id === progress.job_id);
+  if (job) {
+    job.bytes_transferred = progress.bytes_transferred;
+    job.bytes_total = progress.bytes_total;
+    scheduleTransferRender();
+  }
+}
+
+// Uploads finish one by one, so refresh the listing once they settle
+function scheduleObjectsRefresh() {
+  if (objectsRefreshTimeout) {
+    clearTimeout(objectsRefreshTimeout);
+  }
+  objectsRefreshTimeout = window.setTimeout(() => {
+    objectsRefreshTimeout = null;
+    loadObjects();
+  }, 500);
+}
+
+async function runTransferAction(action: string, jobId: string) {
+  try {
+    let job: TransferJob;
+    switch (action) {
+      case "pause":
+        job = await api.pauseTransfer(jobId);
+        break;
+      case "resume":
+        job = await api.resumeTransfer(jobId);
+        break;
+      case "cancel":
+        job = await api.cancelTransfer(jobId);
+        break;
+      case "retry":
+        job = await api.retryTransfer(jobId);
+        break;
+      default:
+        return;
+    }
+    upsertTransfer(job);
+    scheduleTransferRender();
+  } catch (err) {
+    showError(`Failed to ${action} transfer: ${err}`);
+  }
+}
+
+async function clearFinishedTransfers() {
+  try {
+    await api.clearFinishedTransfers();
+    transfers = transfers.filter(job => !isTransferFinished(job));
+    renderTransfers();
+    if (tasks.length === 0 && transfers.length === 0) {
+      hideTaskPanel();
+    }
+  } catch (err) {
+    showError(`Failed to clear transfers: ${err}`);
+  }
+}
+
+function scheduleTransferRender() {
+  if (!transferRenderScheduled) {
+    transferRenderScheduled = true;
+    requestAnimationFrame(() => {
+      transferRenderScheduled = false;
+      renderTransfers();
+    });
+  }
+}
+
+function renderTransfers() {
+  if (transfers.length === 0) {
+    transferListEl.innerHTML = '<div class="task-empty">No transfers</div>';
+    return;
+  }
+  
+  const visibleTransfers = transfers.slice(0, MAX_VISIBLE_TASKS);
+  const hiddenCount = transfers.length - visibleTransfers.length;
+  
+  let html = visibleTransfers.map(job => {
+    const typeIcon = job.direction === "upload"
+      ? `<svg width="16" height="16" viewBox="0 0 16 16" fill="currentColor"><path d="M.5 9.9a.5.5 0 0 1 .5.5v2.5a1 1 0 0 0 1 1h12a1 1 0 0 0 1-1v-2.5a.5.5 0 0 1 1 0v2.5a2 2 0 0 1-2 2H2a2 2 0 0 1-2-2v-2.5a.5.5 0 0 1 .5-.5z"/><path d="M7.646 1.146a.5.5 0 0 1 .708 0l3 3a.5.5 0 0 1-.708.708L8.5 2.707V11.5a.5.5 0 0 1-1 0V2.707L5.354 4.854a.5.5 0 1 1-.708-.708l3-3z"/></svg>`
+      : `<svg width="16" height="16" viewBox="0 0 16 16" fill="currentColor"><path d="M.5 9.9a.5.5 0 0 1 .5.5v2.5a1 1 0 0 0 1 1h12a1 1 0 0 0 1-1v-2.5a.5.5 0 0 1 1 0v2.5a2 2 0 0 1-2 2H2a2 2 0 0 1-2-2v-2.5a.5.5 0 0 1 .5-.5z"/><path d="M7.646 11.854a.5.5 0 0 0 .708 0l3-3a.5.5 0 0 0-.708-.708L8.5 10.293V1.5a.5.5 0 0 0-1 0v8.793L5.354 8.146a.5.5 0 1 0-.708.708l3 3z"/></svg>`;
+    
+    const name = job.direction === "upload"
+      ? job.key.split("/").pop() || job.key
+      : job.local_path.split(/[/\\]/).pop() || job.local_path;
+    const bytes = `${formatSize(job.bytes_transferred)} / ${formatSize(job.bytes_total)}`;
+    const percent = job.bytes_total > 0
+      ? Math.min(100, (job.bytes_transferred / job.bytes_total) * 100)
+      : job.status === "completed" ? 100 : 0;
+    
+    let statusText = "";
+    switch (job.status) {
+      case "queued":
+        statusText = "Queued";
+        break;
+      case "running":
+        statusText = bytes;
+        break;
+      case "paused":
+        statusText = `Paused - ${bytes}`;
+        break;
+      case "completed":
+        statusText = "Completed";
+        break;
+      case "failed":
+        statusText = job.error || "Failed";
+        break;
+      case "cancelled":
+        statusText = "Cancelled";
+        break;
+    }
+    
+    const actions: string[] = [];
+    if (job.status === "queued" || job.status === "running") actions.push("pause");
+    if (job.status === "paused") actions.push("resume");
+    if (!isTransferFinished(job)) actions.push("cancel");
+    if (job.status === "failed" || job.status === "cancelled") actions.push("retry");
+    
+    const buttons = actions.map(action => {
+      const label = action.charAt(0).toUpperCase() + action.slice(1);
+      return `<button class="btn btn-small btn-secondary transfer-action" data-action="${action}" data-id="${job.id}">${label}</button>`;
+    }).join("");
+    
+    return `
+      <div class="transfer-item ${job.status}" data-id="${job.id}">
+        <div class="transfer-row">
+          <div class="task-icon ${job.direction}">${typeIcon}</div>
+          <div class="task-info">
+            <div class="task-name" title="${escapeHtml(job.key)}">${escapeHtml(name)}</div>
+            <div class="task-status ${job.status}" title="${escapeHtml(statusText)}">${escapeHtml(statusText)}</div>
+          </div>
+          <div class="transfer-actions">${buttons}</div>
+        </div>
+        <div class="transfer-progress-bar">
+          <div class="transfer-progress-fill" style="width: ${percent}%"></div>
+        </div>
+      </div>
+    `;
+  }).join("");
+  
+  if (hiddenCount > 0) {
+    html += `<div class="task-hidden-notice">... and ${hiddenCount} more transfers</div>`;
+  }
+  
+  transferListEl.innerHTML = html;
+}
+
 // Search functions
 function handleSearchInput() {
   const value = searchPrefixInput.value;
@@ -1629,6 +1767,14 @@ function setupEventListeners() {
   // Task panel
   clearCompletedBtn.addEventListener("click", clearCompletedTasks);
   closeTaskPanelBtn.addEventListener("click", hideTaskPanel);
+  clearFinishedTransfersBtn.addEventListener("click", clearFinishedTransfers);
+  
+  transferListEl.addEventListener("click", (e) => {
+    const button = (e.target as HTMLElement).closest(".transfer-action") as HTMLElement | null;
+    if (button) {
+      runTransferAction(button.dataset.action!, button.dataset.id!);
+    }
+  });
   
   // Bucket operations
   bucketSelect.addEventListener("change", () => {
@@ -1775,13 +1921,21 @@ function setupTauriEventListeners() {
   });
   
   listen<SyncCompletedPayload>("sync-completed", (event) => {
-    showError(`Sync completed: ${event.payload.files_uploaded} uploaded, ${event.payload.files_downloaded} downloaded`);
+    showError(`Sync completed: ${event.payload.files_uploaded} uploaded, ${event.payload.files_downloaded} downloaded, ${event.payload.files_deleted} deleted`);
   });
   
   listen<SyncErrorPayload>("sync-error", (event) => {
     showError(`Sync error: ${event.payload.error}`);
   });
   
+  listen<TransferJob>("transfer-updated", (event) => {
+    handleTransferUpdated(event.payload);
+  });
+  
+  listen<TransferProgressPayload>("transfer-progress", (event) => {
+    handleTransferProgress(event.payload);
+  });
+  
   setupDragAndDrop();
 }
 
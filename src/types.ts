@@ -44,6 +44,26 @@ export interface ListObjectsResult {
   is_truncated: boolean;
 }
 
+export type BucketVersioning = "disabled" | "enabled" | "suspended";
+
+export interface ObjectVersion {
+  key: string;
+  version_id: string;
+  is_latest: boolean;
+  is_delete_marker: boolean;
+  size: number;
+  last_modified?: string;
+  etag?: string;
+}
+
+export interface ListVersionsResult {
+  versions: ObjectVersion[];
+  common_prefixes: string[];
+  next_key_marker?: string;
+  next_version_id_marker?: string;
+  is_truncated: boolean;
+}
+
 export interface ObjectMetadata {
   content_type?: string;
   content_length: number;
@@ -51,18 +71,101 @@ export interface ObjectMetadata {
   last_modified?: string;
 }
 
-export type SyncDirection = "local_to_remote" | "remote_to_local";
+export type SyncDirection = "local_to_remote" | "remote_to_local" | "bidirectional";
+
+export type ConflictPolicy =
+  | "newest_wins"
+  | "local_wins"
+  | "remote_wins"
+  | "keep_both"
+  | "ask_user";
+
+export type ConflictResolution = "local" | "remote" | "both";
+
+export interface FilterRules {
+  include?: string[];
+  exclude?: string[];
+}
+
+export type CompareMode = "checksum" | "size_mtime" | "size_only" | "existence";
+
+export type SymlinkPolicy = "skip" | "follow" | "store";
+
+export interface SyncOptions {
+  conflict_policy?: ConflictPolicy;
+  filters?: FilterRules;
+  compare_mode?: CompareMode;
+  delete?: boolean;
+  max_delete_percent?: number;
+  symlinks?: SymlinkPolicy;
+  preserve_permissions?: boolean;
+}
+
+export interface SyncConflict {
+  path: string;
+  local_size: number | null;
+  local_modified: string | null;
+  remote_size: number | null;
+  remote_modified: string | null;
+  resolution: ConflictResolution | null;
+}
+
+export type SyncAction =
+  | "skip"
+  | "upload"
+  | "download"
+  | "delete_local"
+  | "delete_remote"
+  | "conflict";
+
+export interface PlannedAction {
+  path: string;
+  action: SyncAction;
+  size: number;
+  reason: string;
+  local_md5: string | null;
+  remote_etag: string | null;
+  conflict: SyncConflict | null;
+}
+
+export interface SyncPlan {
+  bucket: string;
+  prefix: string;
+  local_path: string;
+  direction: SyncDirection;
+  actions: PlannedAction[];
+  errors: SyncFileError[];
+  symlinks: SymlinkPolicy;
+  preserve_permissions: boolean;
+}
+
+export interface SyncFileError {
+  path: string;
+  message: string;
+  code?: string | null;
+}
+
+export interface SyncedFile {
+  path: string;
+  action: SyncAction;
+  size: number;
+}
 
 export interface SyncResult {
   uploaded: number;
   downloaded: number;
   deleted: number;
   skipped: number;
+  conflicts: SyncConflict[];
+  errors: SyncFileError[];
+  bytes_transferred: number;
+  files: SyncedFile[];
 }
 
 export interface DeleteError {
   key: string;
   message: string;
+  code?: string | null;
 }
 
 export interface DeleteObjectsResult {
@@ -70,6 +173,57 @@ export interface DeleteObjectsResult {
   errors: DeleteError[];
 }
 
+export interface PrefixSummary {
+  objects: number;
+  bytes: number;
+}
+
+export interface DeleteProgress {
+  bucket: string;
+  prefix: string;
+  current: number;
+  total: number;
+}
+
+export interface CopyOverrides {
+  content_type?: string | null;
+  metadata?: Record<string, string> | null;
+  tags?: Record<string, string> | null;
+}
+
+export interface ObjectError {
+  key: string;
+  message: string;
+  code?: string | null;
+}
+
+export interface RenamePrefixResult {
+  moved: number;
+  errors: ObjectError[];
+}
+
+export interface CopyPrefixResult {
+  copied: number;
+  errors: ObjectError[];
+}
+
+export interface CopyProgress {
+  source_bucket: string;
+  bucket: string;
+  current: number;
+  total: number;
+  current_key: string;
+}
+
+export interface RenameProgress {
+  bucket: string;
+  from_prefix: string;
+  to_prefix: string;
+  current: number;
+  total: number;
+  current_key: string;
+}
+
 export interface SyncState {
   sync_id: string;
   profile_id: string;
@@ -78,8 +232,60 @@ export interface SyncState {
   local_path: string;
   is_active: boolean;
   last_sync?: string;
+  files_uploaded: number;
+  files_deleted: number;
+  files_downloaded: number;
+  debounce_ms: number;
+  filters: FilterRules;
+  poll_interval_secs?: number;
+  remote_deletes: RemoteDeletePolicy;
+}
+
+export type RemoteDeletePolicy = "keep_local" | "delete_local";
+
+export interface SyncJob {
+  id: string;
+  name: string;
+  profile_id: string;
+  bucket: string;
+  prefix: string;
+  local_path: string;
+  direction: SyncDirection;
+  options: SyncOptions;
+  schedule?: string | null;
+}
+
+export type RunTrigger = "schedule" | "manual" | "keep_sync";
+
+export interface SyncRun {
+  id: string;
+  job_id?: string | null;
+  job_name?: string | null;
+  trigger: RunTrigger;
+  profile_id: string;
+  bucket: string;
+  prefix: string;
+  local_path: string;
+  direction?: SyncDirection | null;
+  started_at: string;
+  finished_at?: string | null;
+  result?: SyncResult | null;
+  files_omitted?: number;
+  error?: string | null;
+  error_code?: string | null;
 }
 
+export interface RunQuery {
+  job_id?: string;
+  trigger?: RunTrigger;
+  bucket?: string;
+  since?: string;
+  failed_only?: boolean;
+  limit?: number;
+}
+
+export type ReportFormat = "json" | "csv";
+
 export interface SyncProgressPayload {
   sync_id: string;
   current: number;
@@ -91,6 +297,7 @@ export interface SyncCompletedPayload {
   sync_id: string;
   files_uploaded: number;
   files_downloaded: number;
+  files_deleted: number;
 }
 
 export interface SyncErrorPayload {
@@ -98,6 +305,36 @@ export interface SyncErrorPayload {
   error: string;
 }
 
+export type TransferDirection = "upload" | "download";
+export type TransferStatus =
+  | "queued"
+  | "running"
+  | "paused"
+  | "completed"
+  | "failed"
+  | "cancelled";
+
+export interface TransferJob {
+  id: string;
+  profile_id: string;
+  direction: TransferDirection;
+  bucket: string;
+  key: string;
+  local_path: string;
+  status: TransferStatus;
+  bytes_transferred: number;
+  bytes_total: number;
+  error?: string;
+  created_at: string;
+  finished_at?: string;
+}
+
+export interface TransferProgressPayload {
+  job_id: string;
+  bytes_transferred: number;
+  bytes_total: number;
+}
+
 export type TaskType = "upload" | "delete" | "sync" | "keepsync";
 export type TaskStatus = "pending" | "running" | "completed" | "failed";
 